@@ -0,0 +1,311 @@
+use std::{
+    fmt::{Display, Formatter},
+    time::SystemTime,
+};
+
+use dashmap::DashMap;
+use serenity::{
+    model::prelude::GuildId,
+    prelude::{RwLock, TypeMap, TypeMapKey},
+};
+
+use crate::{
+    backup::{self, BackupsInProgressKey},
+    command::{CommandReply, ReplyButton, SlashCommand},
+    config::{AdmissionConfig, ServerMap},
+    notifications, power,
+    server_commands::{ServerCommands, ServerError, ServerStatus},
+    stats::HostUsage,
+    ServerKey,
+};
+
+const MIB: u64 = 1024 * 1024;
+const SWAP_BUTTON_ID: &str = "admission-swap";
+// discord rejects messages with longer component ids and button labels
+const MAX_CUSTOM_ID_LENGTH: usize = 100;
+const MAX_LABEL_LENGTH: usize = 80;
+
+pub struct AdmissionKey;
+impl TypeMapKey for AdmissionKey {
+    type Value = AdmissionConfig;
+}
+
+/// When each server was last targeted by a command, used to find the least recently used server
+pub struct ServerActivityKey;
+impl TypeMapKey for ServerActivityKey {
+    type Value = DashMap<String, SystemTime>;
+}
+
+/// Why a server couldn't be started, and what could be stopped to make room for it
+#[derive(Debug)]
+pub struct CapacityError {
+    pub reason: String,
+    /// Servers currently using capacity, as (name, memory weight in MiB)
+    pub running: Vec<(String, u64)>,
+    pub least_recently_used: Option<String>,
+}
+
+impl Display for CapacityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.reason)?;
+
+        if !self.running.is_empty() {
+            let running = self
+                .running
+                .iter()
+                .map(|(name, weight)| {
+                    if *weight > 0 {
+                        format!("{} ({} MiB)", name, weight)
+                    } else {
+                        name.clone()
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            write!(f, "\nServers using capacity: {}", running.join(", "))?;
+        }
+
+        if let Some(lru) = &self.least_recently_used {
+            write!(
+                f,
+                "\n{} is the least recently used; stop it to make room",
+                lru
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Records that a command targeted the server
+pub fn record_activity(data: &TypeMap, server_name: &str) {
+    let activity = data.get::<ServerActivityKey>().unwrap();
+
+    activity.insert(server_name.to_string(), SystemTime::now());
+}
+
+/// Checks that the server can be started, then wakes its host, before the bot starts, restarts
+/// or resumes it. Every start the bot makes goes through here, from commands, the api and swap
+/// buttons; servers restarted after a backup quiesced them held their capacity while stopped, so
+/// they skip it.
+pub async fn admit(
+    data: &RwLock<TypeMap>,
+    guild_id: GuildId,
+    server_name: &str,
+    command: SlashCommand,
+) -> Result<(), ServerError> {
+    // copied out, so the data lock isn't held while statuses are read over the network
+    let (config, servers, activity) = {
+        let data = data.read().await;
+
+        // a backup archives the data of a stopped server, which must not change underneath it
+        if data
+            .get::<BackupsInProgressKey>()
+            .unwrap()
+            .contains(server_name)
+        {
+            return Err(ServerError::CommandFailed(
+                command,
+                format!(
+                    "A backup of server {} is in progress; start it once the backup finishes",
+                    server_name
+                ),
+            ));
+        }
+
+        (
+            data.get::<AdmissionKey>().unwrap().clone(),
+            data.get::<ServerKey>().unwrap().clone(),
+            data.get::<ServerActivityKey>().unwrap().clone(),
+        )
+    };
+
+    check(&config, &servers, &activity, guild_id, server_name, command)?;
+
+    // only once there's room, so a refused start never wakes a host for nothing
+    match servers.get(server_name) {
+        Some(server) => power::ensure_awake(data, server, command).await,
+        None => Ok(()),
+    }
+}
+
+/// Checks that starting the server wouldn't exceed the configured limits.
+/// Servers that are running or paused already hold their capacity, so they always pass; servers
+/// whose status can't be read, like those on a host that's asleep, need it like stopped ones.
+fn check(
+    config: &AdmissionConfig,
+    servers: &ServerMap,
+    activity: &DashMap<String, SystemTime>,
+    guild_id: GuildId,
+    server_name: &str,
+    command: SlashCommand,
+) -> Result<(), ServerError> {
+    if config.max_running.is_none() && config.min_free_memory_mib.is_none() {
+        return Ok(());
+    }
+
+    let server = match servers.get(server_name) {
+        Some(server) => server,
+        None => return Ok(()),
+    };
+
+    if let Ok(status) = server.get_status() {
+        if !matches!(status, ServerStatus::Stopped) {
+            return Ok(());
+        }
+    }
+
+    let mut running = servers
+        .iter()
+        .filter(|(name, _)| *name != server_name)
+        .filter_map(|(name, server)| match server.get_status() {
+            Ok(status @ (ServerStatus::Running | ServerStatus::Paused)) => {
+                Some((name, server, status))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    running.sort_by_key(|(name, _, _)| *name);
+
+    let mut reason = None;
+
+    if let Some(max_running) = config.max_running {
+        if running.len() >= max_running {
+            reason = Some(format!(
+                "Cannot start server {}: {} of {} allowed servers are already running",
+                server_name,
+                running.len(),
+                max_running
+            ));
+        }
+    }
+
+    if let (None, Some(min_free)) = (&reason, config.min_free_memory_mib) {
+        let available = HostUsage::read()
+            .map_err(|err| ServerError::CommandFailed(command, err.to_string()))?
+            .memory_available
+            / MIB;
+        let needed = min_free + server.get_memory_weight();
+
+        if available < needed {
+            reason = Some(format!(
+                "Cannot start server {}: only {} MiB of host memory is free, but it needs {} MiB with {} MiB kept free",
+                server_name,
+                available,
+                server.get_memory_weight(),
+                min_free
+            ));
+        }
+    }
+
+    match reason {
+        Some(reason) => {
+            // only offer servers the guild can manage, and that can be stopped as they are
+            let least_recently_used = running
+                .iter()
+                .filter(|(_, server, status)| {
+                    server.get_guild_ids().contains(&guild_id)
+                        && matches!(status, ServerStatus::Running)
+                })
+                .min_by_key(|(name, _, _)| {
+                    activity
+                        .get(*name)
+                        .map_or(SystemTime::UNIX_EPOCH, |time| *time)
+                })
+                .map(|(name, _, _)| name.to_string());
+
+            Err(ServerError::InsufficientCapacity(CapacityError {
+                reason,
+                running: running
+                    .iter()
+                    .map(|(name, server, _)| (name.to_string(), server.get_memory_weight()))
+                    .collect(),
+                least_recently_used,
+            }))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Builds the reply for a failed check, offering to stop the least recently used server
+pub fn reply(server_name: &str, err: ServerError) -> CommandReply {
+    let mut reply = CommandReply::error(&err);
+
+    if let ServerError::InsufficientCapacity(CapacityError {
+        least_recently_used: Some(lru),
+        ..
+    }) = &err
+    {
+        let custom_id = serde_json::to_string(&[SWAP_BUTTON_ID, lru, server_name])
+            .expect("Failed to serialize button id");
+
+        // the reply already names the server to stop, so long names just go without the button
+        if custom_id.chars().count() <= MAX_CUSTOM_ID_LENGTH {
+            reply.buttons.push(ReplyButton {
+                label: format!("Stop {} and start {}", lru, server_name)
+                    .chars()
+                    .take(MAX_LABEL_LENGTH)
+                    .collect(),
+                custom_id,
+            });
+        }
+    }
+
+    reply
+}
+
+/// Parses the (server to stop, server to start) from a swap button id
+pub fn parse_swap_id(custom_id: &str) -> Option<(String, String)> {
+    match serde_json::from_str::<Vec<String>>(custom_id) {
+        Ok(ids) if ids.len() == 3 && ids[0] == SWAP_BUTTON_ID => {
+            Some((ids[1].clone(), ids[2].clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Stops one server to make room for another, then starts it
+pub async fn run_swap(
+    data: &RwLock<TypeMap>,
+    guild_id: GuildId,
+    stop_name: &str,
+    start_name: &str,
+) -> String {
+    let servers = data.read().await.get::<ServerKey>().unwrap().clone();
+
+    let (stop, start) = match (servers.get(stop_name), servers.get(start_name)) {
+        (Some(stop), Some(start))
+            if stop.get_guild_ids().contains(&guild_id)
+                && start.get_guild_ids().contains(&guild_id) =>
+        {
+            (stop, start)
+        }
+        _ => return "Server not found".to_string(),
+    };
+
+    {
+        let data = data.read().await;
+        record_activity(&data, start_name);
+
+        // the caller checks both servers afterwards, which clears the marks
+        notifications::expect_change(&data, stop_name);
+        notifications::expect_change(&data, start_name);
+    }
+
+    if let Err(err) = stop.stop_server() {
+        return err.to_string();
+    }
+    backup::backup_on_stop(&*data.read().await, stop_name);
+
+    let admitted = admit(data, guild_id, start_name, SlashCommand::Start).await;
+
+    match admitted.and_then(|_| start.start_server()) {
+        Ok(_) => format!(
+            "Stopped server {} and started server {} -- status: {:?}",
+            stop_name,
+            start_name,
+            start.get_status()
+        ),
+        Err(err) => format!("Stopped server {}; {}", stop_name, err),
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    fmt::{Display, Formatter},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, GuildId, UserId},
+    prelude::{RwLock, TypeMap, TypeMapKey},
+};
+
+use tracing::error;
+
+use crate::{notifications, store::StoreKey};
+
+// results are shortened so entries stay readable in the audit channel and history
+const MAX_RESULT_LENGTH: usize = 300;
+
+/// Channel every server management action is posted to, if configured
+pub struct AuditChannelKey;
+impl TypeMapKey for AuditChannelKey {
+    type Value = Option<ChannelId>;
+}
+
+/// Who took a server management action
+#[derive(Debug, Clone)]
+pub enum AuditActor {
+    User(UserId),
+    // a client of the http api, by its configured name
+    Api(String),
+}
+
+impl Display for AuditActor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditActor::User(user_id) => write!(f, "<@{}>", user_id.0),
+            AuditActor::Api(name) => write!(f, "API client `{}`", name),
+        }
+    }
+}
+
+/// A server management action taken by a user or api client
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub time: SystemTime,
+    pub guild_id: GuildId,
+    pub actor: AuditActor,
+    pub server_name: Option<String>,
+    pub command: String,
+    pub result: String,
+    pub duration: Duration,
+}
+
+impl Display for AuditEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let timestamp = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        // discord renders the timestamp in the reader's timezone
+        write!(f, "<t:{}:f> {} {}", timestamp, self.actor, self.command)?;
+
+        if let Some(server_name) = &self.server_name {
+            write!(f, " {}", server_name)?;
+        }
+
+        write!(
+            f,
+            " ({}): {}",
+            humantime::format_duration(Duration::from_millis(self.duration.as_millis() as u64)),
+            self.result
+        )
+    }
+}
+
+/// Stores the action in the audit log and posts it to the audit channel
+pub async fn record(data: &RwLock<TypeMap>, http: &Arc<Http>, mut entry: AuditEntry) {
+    entry.result = summarize(&entry.result);
+
+    let (store, channel_id) = {
+        let data = data.read().await;
+
+        (
+            data.get::<StoreKey>().unwrap().clone(),
+            *data.get::<AuditChannelKey>().unwrap(),
+        )
+    };
+
+    if let Err(err) = store.record_audit(&entry) {
+        error!(%err, "Failed to record audit entry");
+    }
+
+    // posted in the background, so a slow or rate limited channel never delays replies
+    if let Some(channel_id) = channel_id {
+        let http = http.clone();
+        tokio::spawn(async move {
+            notifications::post(&http, channel_id, None, &entry.to_string()).await;
+        });
+    }
+}
+
+fn summarize(result: &str) -> String {
+    let result = result.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    match result.char_indices().nth(MAX_RESULT_LENGTH) {
+        Some((index, _)) => format!("{}...", &result[..index]),
+        None => result,
+    }
+}
@@ -0,0 +1,619 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use dashmap::DashSet;
+use serenity::{
+    http::Http,
+    prelude::{RwLock, TypeMap, TypeMapKey},
+};
+use tokio::sync::mpsc;
+
+use tracing::{error, info, instrument};
+
+use crate::{
+    command::SlashCommand,
+    config::{BackupConfig, BackupQuiesce, BackupSource, ServerType},
+    notifications,
+    server_commands::{run_async_command, ServerCommands, ServerError, ServerStatus},
+    stats::format_bytes,
+    store::StoreKey,
+    ServerKey,
+};
+
+const ARCHIVE_EXTENSION: &str = ".tar.zst";
+
+// the reason given for backups taken after the bot stops a server
+const STOP_REASON: &str = "stop";
+
+// how often servers are checked for a due scheduled backup
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Servers with a backup in progress, so backups of the same server never overlap
+pub struct BackupsInProgressKey;
+impl TypeMapKey for BackupsInProgressKey {
+    type Value = Arc<DashSet<String>>;
+}
+
+/// Automatic backups waiting to run in the background, as (server name, reason)
+pub struct BackupQueueKey;
+impl TypeMapKey for BackupQueueKey {
+    type Value = mpsc::UnboundedSender<(String, &'static str)>;
+}
+
+/// Marks a backup of the server as in progress until dropped
+pub struct BackupGuard {
+    in_progress: Arc<DashSet<String>>,
+    server_name: String,
+}
+
+impl Drop for BackupGuard {
+    fn drop(&mut self) {
+        self.in_progress.remove(&self.server_name);
+    }
+}
+
+/// Marks a backup of the server as in progress, or returns `None` if one already is
+pub fn begin_backup(data: &TypeMap, server_name: &str) -> Option<BackupGuard> {
+    let in_progress = data.get::<BackupsInProgressKey>().unwrap().clone();
+
+    if !in_progress.insert(server_name.to_string()) {
+        return None;
+    }
+
+    Some(BackupGuard {
+        in_progress,
+        server_name: server_name.to_string(),
+    })
+}
+
+/// Queues a backup of a server the bot just stopped, if it's configured to be backed up on stop.
+/// Servers that stop on their own aren't backed up, since their data may be inconsistent.
+/// The backup is skipped if the server has been started again by the time it runs.
+pub fn backup_on_stop(data: &TypeMap, server_name: &str) {
+    let servers = data.get::<ServerKey>().unwrap();
+    let backup_on_stop = servers
+        .get(server_name)
+        .and_then(ServerType::get_backup)
+        .is_some_and(|backup| backup.backup_on_stop);
+
+    if backup_on_stop {
+        let queue = data.get::<BackupQueueKey>().unwrap();
+        let _ = queue.send((server_name.to_string(), STOP_REASON));
+    }
+}
+
+/// Runs queued backups, each in its own task so a large archive never holds up the others
+pub async fn run_queued_backups(
+    data: Arc<RwLock<TypeMap>>,
+    http: Arc<Http>,
+    mut queue: mpsc::UnboundedReceiver<(String, &'static str)>,
+) {
+    while let Some((server_name, reason)) = queue.recv().await {
+        let (data, http) = (data.clone(), http.clone());
+
+        tokio::spawn(async move {
+            run_automatic_backup(&data, &http, &server_name, reason).await;
+        });
+    }
+}
+
+/// Backs up running servers whose newest archive is older than their configured interval
+pub async fn schedule_backups(data: Arc<RwLock<TypeMap>>, http: Arc<Http>) {
+    loop {
+        tokio::time::sleep(SCHEDULE_CHECK_INTERVAL).await;
+
+        let (servers, store) = {
+            let data = data.read().await;
+
+            (
+                data.get::<ServerKey>().unwrap().clone(),
+                data.get::<StoreKey>().unwrap().clone(),
+            )
+        };
+
+        for (server_name, server) in servers.iter() {
+            let config = match server.get_backup() {
+                Some(config) => config,
+                None => continue,
+            };
+
+            let interval = match config.interval_mins {
+                Some(mins) => Duration::from_secs(mins * 60),
+                None => continue,
+            };
+
+            if !matches!(server.get_status(), Ok(ServerStatus::Running)) {
+                continue;
+            }
+
+            let newest = match list_backups(server_name, config) {
+                Ok(backups) => backups.first().map(|backup| backup.created),
+                Err(err) => {
+                    error!(server = %server_name, %err, "Failed to list backups");
+                    continue;
+                }
+            };
+
+            // attempts are stored too, so a failing backup isn't retried every check
+            let schedule_name = format!("backup:{}", server_name);
+            let last_attempt = match store.last_run(&schedule_name) {
+                Ok(last_attempt) => last_attempt,
+                Err(err) => {
+                    error!(server = %server_name, %err, "Failed to read backup schedule");
+                    continue;
+                }
+            };
+
+            let elapsed = |time: Option<SystemTime>| {
+                time.is_none_or(|time| {
+                    SystemTime::now()
+                        .duration_since(time)
+                        .is_ok_and(|age| age >= interval)
+                })
+            };
+
+            if elapsed(newest) && elapsed(last_attempt) {
+                if let Err(err) = store.set_last_run(&schedule_name, SystemTime::now()) {
+                    error!(server = %server_name, %err, "Failed to store backup schedule");
+                }
+
+                run_automatic_backup(&data, &http, server_name, "scheduled").await;
+            }
+        }
+    }
+}
+
+/// Backs up the server outside of a command, posting a summary to its notification channel
+#[instrument(skip(data, http))]
+pub async fn run_automatic_backup(
+    data: &RwLock<TypeMap>,
+    http: &Http,
+    server_name: &str,
+    reason: &str,
+) {
+    let servers = data.read().await.get::<ServerKey>().unwrap().clone();
+    let (server, config) = match servers.get(server_name) {
+        Some(server) => match server.get_backup() {
+            Some(config) => (server, config),
+            None => return,
+        },
+        None => return,
+    };
+
+    let _guard = match begin_backup(&*data.read().await, server_name) {
+        Some(guard) => guard,
+        None => {
+            info!("Skipping backup; a backup is in progress");
+            return;
+        }
+    };
+
+    // starts are refused while the backup is in progress, so a server that's still stopped
+    // stays stopped; one started again since the stop is left alone rather than stopped again
+    if reason == STOP_REASON && !matches!(server.get_status(), Ok(ServerStatus::Stopped)) {
+        info!("Skipping backup; the server was started again");
+        return;
+    }
+
+    let content = match create_backup(server_name, server, config).await {
+        Ok(summary) => {
+            let mut content = format!(
+                "Created {} backup {} ({}) of server {} in {}",
+                reason,
+                summary.backup.name,
+                format_bytes(summary.backup.size),
+                server_name,
+                humantime::format_duration(Duration::from_secs(summary.duration.as_secs()))
+            );
+
+            if !summary.pruned.is_empty() {
+                content.push_str(&format!(
+                    "\nRemoved old backups: {}",
+                    summary.pruned.join(", ")
+                ));
+            }
+
+            content
+        }
+        Err(err) => format!("The {reason} backup of server {server_name} failed: {err}"),
+    };
+
+    info!(%content, "Finished backup");
+
+    if let Some(notifications) = server.get_notifications() {
+        notifications::post(http, notifications.channel_id, None, &content).await;
+    }
+}
+
+/// An archive of server data
+#[derive(Debug, Clone)]
+pub struct BackupInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub created: SystemTime,
+}
+
+/// The result of creating a backup
+#[derive(Debug, Clone)]
+pub struct BackupSummary {
+    pub backup: BackupInfo,
+    pub duration: Duration,
+    /// Archives removed to stay within the retention count
+    pub pruned: Vec<String>,
+}
+
+/// Snapshots the server data into a new timestamped archive, pausing or stopping
+/// the server around the snapshot if configured, then prunes old archives.
+pub async fn create_backup(
+    server_name: &str,
+    server: &ServerType,
+    config: &BackupConfig,
+) -> Result<BackupSummary, ServerError> {
+    let started = Instant::now();
+    let backup = snapshot(server_name, server, config).await?;
+    let pruned = prune_backups(server_name, config).map_err(backup_failed)?;
+
+    Ok(BackupSummary {
+        backup,
+        duration: started.elapsed(),
+        pruned,
+    })
+}
+
+async fn snapshot(
+    server_name: &str,
+    server: &ServerType,
+    config: &BackupConfig,
+) -> Result<BackupInfo, ServerError> {
+    let source = resolve_source(server, &config.source).await?;
+
+    fs::create_dir_all(&config.destination).map_err(backup_failed)?;
+
+    let timestamp = timestamp(SystemTime::now());
+    let name = format!("{}-{}{}", server_name, timestamp, ARCHIVE_EXTENSION);
+    let path = config.destination.join(&name);
+
+    let quiesced = match (server.get_status(), config.quiesce) {
+        (Ok(ServerStatus::Running), BackupQuiesce::Pause) => server.pause_server().map(|_| true),
+        (Ok(ServerStatus::Running), BackupQuiesce::Stop) => server.stop_server().map(|_| true),
+        _ => Ok(false),
+    }?;
+
+    let level = config.compression_level;
+    let archive_path = path.clone();
+    let res = tokio::task::spawn_blocking(move || write_archive(&source, &archive_path, level))
+        .await
+        .map_err(backup_failed)
+        .and_then(|res| res.map_err(backup_failed));
+
+    if quiesced {
+        let resumed = match config.quiesce {
+            BackupQuiesce::Pause => server.unpause_server(),
+            BackupQuiesce::Stop => server.start_server(),
+            BackupQuiesce::None => Ok(()),
+        };
+
+        if let Err(err) = resumed {
+            error!(server = %server_name, %err, "Failed to resume server after backup");
+        }
+    }
+
+    res?;
+
+    backup_info(&path).map_err(backup_failed)
+}
+
+/// Lists the archives of the server, newest first
+pub fn list_backups(server_name: &str, config: &BackupConfig) -> io::Result<Vec<BackupInfo>> {
+    let entries = match fs::read_dir(&config.destination) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    let prefix = format!("{}-", server_name);
+    let mut backups = vec![];
+
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+
+        // the timestamp is checked too, so servers sharing a name prefix don't share archives
+        let timestamp = file_name
+            .strip_prefix(&prefix)
+            .and_then(|name| name.strip_suffix(ARCHIVE_EXTENSION));
+
+        if timestamp.is_some_and(is_timestamp) {
+            backups.push(backup_info(&entry.path())?);
+        }
+    }
+
+    backups.sort_by(|a, b| b.created.cmp(&a.created).then(b.name.cmp(&a.name)));
+
+    Ok(backups)
+}
+
+/// Replaces the server data with the contents of an archive. The server must be stopped;
+/// the current data is backed up first so the restore can be undone, and nothing is pruned
+/// so the restored archive is kept.
+pub async fn restore_backup(
+    server_name: &str,
+    server: &ServerType,
+    config: &BackupConfig,
+    archive_name: &str,
+) -> Result<BackupInfo, ServerError> {
+    match server.get_status() {
+        Ok(ServerStatus::Stopped) => {}
+        Ok(status) => return Err(ServerError::StatusError(status, SlashCommand::Backup)),
+        Err(err) => return Err(err),
+    }
+
+    // only archives belonging to the server can be restored, which also rules out path traversal
+    let archive = list_backups(server_name, config)
+        .map_err(backup_failed)?
+        .into_iter()
+        .find(|backup| backup.name == archive_name)
+        .ok_or_else(|| {
+            ServerError::CommandFailed(
+                SlashCommand::Backup,
+                format!("Backup {} not found", archive_name),
+            )
+        })?;
+
+    let previous = snapshot(server_name, server, config).await?;
+    let source = resolve_source(server, &config.source).await?;
+
+    tokio::task::spawn_blocking(move || extract_archive(&archive.path, &source))
+        .await
+        .map_err(backup_failed)?
+        .map_err(backup_failed)?;
+
+    Ok(previous)
+}
+
+fn prune_backups(server_name: &str, config: &BackupConfig) -> io::Result<Vec<String>> {
+    let backups = list_backups(server_name, config)?;
+    let mut pruned = vec![];
+
+    for backup in backups.into_iter().skip(config.retention.max(1)) {
+        fs::remove_file(&backup.path)?;
+        pruned.push(backup.name);
+    }
+
+    Ok(pruned)
+}
+
+async fn resolve_source(
+    server: &ServerType,
+    source: &BackupSource,
+) -> Result<PathBuf, ServerError> {
+    // volumes of a remote engine are mounted on another machine, where the bot can't archive them
+    let remote = matches!(server, ServerType::Docker(docker) if docker.engine.is_some());
+
+    match source {
+        BackupSource::Path(path) => Ok(path.clone()),
+        BackupSource::Volume(_) if remote => Err(ServerError::CommandFailed(
+            SlashCommand::Backup,
+            "Volumes can only be backed up from the local docker engine".to_string(),
+        )),
+        BackupSource::Volume(volume) => {
+            let mountpoint = run_async_command(
+                SlashCommand::Backup,
+                "docker",
+                &["volume", "inspect", "--format", "{{.Mountpoint}}", volume],
+            )
+            .await?;
+
+            Ok(PathBuf::from(mountpoint.trim()))
+        }
+    }
+}
+
+fn write_archive(source: &Path, path: &Path, level: i32) -> io::Result<()> {
+    // written under a temporary name so partial archives are never listed
+    let partial = path.with_extension("partial");
+
+    // neither file is ever replaced, so a backup can't overwrite another with the same name
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&partial)?;
+
+    let res = (|| {
+        let encoder = zstd::Encoder::new(file, level)?;
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+        builder.append_dir_all(".", source)?;
+        builder.into_inner()?.finish()?.sync_all()?;
+        fs::hard_link(&partial, path)
+    })();
+
+    let _ = fs::remove_file(&partial);
+    res
+}
+
+fn extract_archive(path: &Path, destination: &Path) -> io::Result<()> {
+    fs::create_dir_all(destination)?;
+
+    for entry in fs::read_dir(destination)? {
+        let path = entry?.path();
+        if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(path)?)?);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_ownerships(true);
+    archive.unpack(destination)
+}
+
+fn backup_info(path: &Path) -> io::Result<BackupInfo> {
+    let metadata = fs::metadata(path)?;
+
+    Ok(BackupInfo {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        path: path.to_path_buf(),
+        size: metadata.len(),
+        created: metadata.modified()?,
+    })
+}
+
+// e.g. 2023-03-21T184502.123Z; colons are left out so the name is valid on every filesystem,
+// and milliseconds are kept so backups made in the same second get different names
+fn timestamp(time: SystemTime) -> String {
+    humantime::format_rfc3339_millis(time)
+        .to_string()
+        .replace(':', "")
+}
+
+fn is_timestamp(timestamp: &str) -> bool {
+    timestamp.len() == "2023-03-21T184502.123Z".len()
+        && timestamp.ends_with('Z')
+        && timestamp.chars().next().is_some_and(|c| c.is_ascii_digit())
+}
+
+fn backup_failed<E: ToString>(err: E) -> ServerError {
+    ServerError::CommandFailed(SlashCommand::Backup, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn config(destination: &Path, retention: usize) -> BackupConfig {
+        BackupConfig {
+            source: BackupSource::Path(PathBuf::from("/srv/valheim")),
+            destination: destination.to_path_buf(),
+            retention,
+            compression_level: 3,
+            quiesce: BackupQuiesce::None,
+            backup_on_stop: false,
+            interval_mins: None,
+        }
+    }
+
+    // creates an archive modified the given number of seconds after the epoch
+    fn archive(dir: &Path, name: &str, modified_secs: u64) {
+        let file = File::create(dir.join(name)).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(modified_secs))
+            .unwrap();
+    }
+
+    fn names(backups: &[BackupInfo]) -> Vec<&str> {
+        backups.iter().map(|backup| backup.name.as_str()).collect()
+    }
+
+    #[test]
+    fn formats_timestamp_without_colons() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1679424302123);
+
+        assert_eq!(timestamp(time), "2023-03-21T184502.123Z");
+        assert!(is_timestamp(&timestamp(time)));
+    }
+
+    #[test]
+    fn rejects_other_timestamps() {
+        assert!(!is_timestamp("2023-03-21T184502Z"));
+        assert!(!is_timestamp("2023-03-21T18:45:02.123Z"));
+        assert!(!is_timestamp("old-03-21T184502.123Z"));
+        assert!(!is_timestamp("2023-03-21T184502.123+"));
+        assert!(!is_timestamp(""));
+    }
+
+    #[test]
+    fn lists_only_the_servers_archives_newest_first() {
+        let dir = TempDir::new().unwrap();
+        archive(dir.path(), "valheim-2023-03-21T184502.123Z.tar.zst", 100);
+        archive(dir.path(), "valheim-2023-03-22T090000.000Z.tar.zst", 200);
+        // a server whose name starts with this one's
+        archive(
+            dir.path(),
+            "valheim-old-2023-03-23T090000.000Z.tar.zst",
+            300,
+        );
+        archive(dir.path(), "valheim-2023-03-24T090000.000Z.partial", 400);
+        archive(dir.path(), "valheim-notes.txt", 500);
+
+        let backups = list_backups("valheim", &config(dir.path(), 5)).unwrap();
+
+        assert_eq!(
+            names(&backups),
+            [
+                "valheim-2023-03-22T090000.000Z.tar.zst",
+                "valheim-2023-03-21T184502.123Z.tar.zst"
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_archives_made_at_the_same_time_by_name() {
+        let dir = TempDir::new().unwrap();
+        archive(dir.path(), "valheim-2023-03-21T184502.123Z.tar.zst", 100);
+        archive(dir.path(), "valheim-2023-03-21T184502.456Z.tar.zst", 100);
+
+        let backups = list_backups("valheim", &config(dir.path(), 5)).unwrap();
+
+        assert_eq!(
+            names(&backups),
+            [
+                "valheim-2023-03-21T184502.456Z.tar.zst",
+                "valheim-2023-03-21T184502.123Z.tar.zst"
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_nothing_without_destination() {
+        let dir = TempDir::new().unwrap();
+
+        let backups = list_backups("valheim", &config(&dir.path().join("missing"), 5)).unwrap();
+
+        assert!(backups.is_empty());
+    }
+
+    #[test]
+    fn prunes_oldest_archives_beyond_retention() {
+        let dir = TempDir::new().unwrap();
+        archive(dir.path(), "valheim-2023-03-21T000000.000Z.tar.zst", 100);
+        archive(dir.path(), "valheim-2023-03-22T000000.000Z.tar.zst", 200);
+        archive(dir.path(), "valheim-2023-03-23T000000.000Z.tar.zst", 300);
+        archive(dir.path(), "valheim-old-2023-03-20T000000.000Z.tar.zst", 50);
+
+        let pruned = prune_backups("valheim", &config(dir.path(), 2)).unwrap();
+
+        assert_eq!(pruned, ["valheim-2023-03-21T000000.000Z.tar.zst"]);
+        assert!(!dir
+            .path()
+            .join("valheim-2023-03-21T000000.000Z.tar.zst")
+            .exists());
+        assert!(dir
+            .path()
+            .join("valheim-old-2023-03-20T000000.000Z.tar.zst")
+            .exists());
+    }
+
+    #[test]
+    fn keeps_newest_archive_without_retention() {
+        let dir = TempDir::new().unwrap();
+        archive(dir.path(), "valheim-2023-03-21T000000.000Z.tar.zst", 100);
+        archive(dir.path(), "valheim-2023-03-22T000000.000Z.tar.zst", 200);
+
+        let pruned = prune_backups("valheim", &config(dir.path(), 0)).unwrap();
+
+        assert_eq!(pruned, ["valheim-2023-03-21T000000.000Z.tar.zst"]);
+    }
+}
@@ -1,15 +1,34 @@
+use std::{sync::Arc, time::Duration};
+
+use regex::Regex;
 use serenity::{
     builder::{CreateApplicationCommand, CreateApplicationCommandOption},
     json::Value,
     model::prelude::{command::*, interaction::application_command::CommandDataOption, GuildId},
+    prelude::{RwLock, TypeMap},
 };
 use strum::{Display, EnumIter, EnumString};
 
+use tracing::debug;
+
 use crate::{
-    config::{ServerMap, ServerType},
-    server_commands::ServerCommands,
+    admission, backup,
+    config::{HostMap, ServerMap, ServerType},
+    join,
+    logs::{
+        self, LogFollow, LogQuery, DEFAULT_FOLLOW_TIMEOUT, DEFAULT_LOG_LINES, MAX_FOLLOW_TIMEOUT,
+        MAX_LOG_LINES,
+    },
+    notifications,
+    power::{self, HostsKey},
+    server_commands::{self, ServerCommands, ServerStatus},
+    stats::{self, HostUsage},
+    store::StoreKey,
+    update, ServerKey,
 };
 
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
 #[derive(Debug, Clone, Copy, EnumIter, EnumString, Display)]
 pub enum SlashCommand {
     #[strum(serialize = "connect-server")]
@@ -30,18 +49,84 @@ pub enum SlashCommand {
     Status,
     #[strum(serialize = "list-servers")]
     List,
+    #[strum(serialize = "server-logs")]
+    Logs,
+    #[strum(serialize = "host-status")]
+    HostStatus,
+    #[strum(serialize = "server-backup")]
+    Backup,
+    #[strum(serialize = "server-update")]
+    Update,
+    #[strum(serialize = "server-history")]
+    History,
 }
 
 type ServerNames<'a> = [&'a String];
 
+/// The reply to a slash command
+#[derive(Default)]
+pub struct CommandReply {
+    pub content: String,
+    /// Files to upload with the reply, as (filename, data)
+    pub attachments: Vec<(String, Vec<u8>)>,
+    /// Logs to stream into a thread on the reply
+    pub follow: Option<LogFollow>,
+    pub buttons: Vec<ReplyButton>,
+    pub links: Vec<ReplyLink>,
+    /// True if the command didn't do what was asked
+    pub failed: bool,
+}
+
+/// A button on a reply, handled as a component interaction
+pub struct ReplyButton {
+    pub label: String,
+    pub custom_id: String,
+}
+
+/// A button on a reply that opens a web link
+pub struct ReplyLink {
+    pub label: String,
+    pub url: String,
+}
+
+impl CommandReply {
+    pub fn error<E: ToString>(err: E) -> Self {
+        Self {
+            content: err.to_string(),
+            failed: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<String> for CommandReply {
+    fn from(content: String) -> Self {
+        Self {
+            content,
+            ..Default::default()
+        }
+    }
+}
+
 impl SlashCommand {
     const SERVER_OPTION: &'static str = "server";
+    const ENDPOINT_OPTION: &'static str = "endpoint";
+    const LINES_OPTION: &'static str = "lines";
+    const SINCE_OPTION: &'static str = "since";
+    const FOLLOW_OPTION: &'static str = "follow";
+    const FILTER_OPTION: &'static str = "filter";
+    const TIMEOUT_OPTION: &'static str = "timeout";
+    const ARCHIVE_OPTION: &'static str = "archive";
+    const CONFIRM_OPTION: &'static str = "confirm";
+    const BACKUP_CREATE: &'static str = "create";
+    const BACKUP_LIST: &'static str = "list";
+    const BACKUP_RESTORE: &'static str = "restore";
 
     pub fn register<'a>(
         &'a self,
         servers: &'_ ServerNames,
         command: &'a mut CreateApplicationCommand,
-    ) -> &mut CreateApplicationCommand {
+    ) -> &'a mut CreateApplicationCommand {
         let name = format!("{}", self);
         let description = self.description();
         command
@@ -76,11 +161,22 @@ impl SlashCommand {
             }
             SlashCommand::Status => "Returns the status of the server".to_string(),
             SlashCommand::List => "Returns a list of available servers".to_string(),
+            SlashCommand::Logs => "Returns the most recent logs of the server".to_string(),
+            SlashCommand::HostStatus => {
+                "Returns the resource usage of the host and every server".to_string()
+            }
+            SlashCommand::Backup => {
+                "Creates, lists, or restores backups of the server data".to_string()
+            }
+            SlashCommand::Update => {
+                "Updates the server to the newest version, rolling back if it fails".to_string()
+            }
+            SlashCommand::History => "Returns the recent actions taken on the server".to_string(),
         }
     }
 
     fn options(&self, servers: &ServerNames) -> Vec<CreateApplicationCommandOption> {
-        if matches!(self, SlashCommand::List) {
+        if matches!(self, SlashCommand::List | SlashCommand::HostStatus) {
             vec![]
         } else {
             let description = match self {
@@ -92,7 +188,11 @@ impl SlashCommand {
                 SlashCommand::Unpause => "The server to unpause",
                 SlashCommand::Resume => "The server to resume",
                 SlashCommand::Status => "The server to check the status of",
-                SlashCommand::List => unreachable!(),
+                SlashCommand::Logs => "The server to get the logs of",
+                SlashCommand::Backup => "The server to manage backups of",
+                SlashCommand::Update => "The server to update",
+                SlashCommand::History => "The server to get the history of",
+                SlashCommand::List | SlashCommand::HostStatus => unreachable!(),
             };
             let mut option = CreateApplicationCommandOption::default();
 
@@ -108,33 +208,143 @@ impl SlashCommand {
                 option.add_string_choice(server, server);
             }
 
-            vec![option]
+            let mut options = vec![option];
+
+            if matches!(self, SlashCommand::Connect) {
+                let mut endpoint = CreateApplicationCommandOption::default();
+                endpoint
+                    .name(Self::ENDPOINT_OPTION)
+                    .description("Only get the connection string for this endpoint, e.g. lan")
+                    .kind(CommandOptionType::String)
+                    .required(false);
+
+                options.push(endpoint);
+            }
+
+            if matches!(self, SlashCommand::Logs) {
+                let mut lines = CreateApplicationCommandOption::default();
+                lines
+                    .name(Self::LINES_OPTION)
+                    .description(format!(
+                        "Number of lines to fetch (default {})",
+                        DEFAULT_LOG_LINES
+                    ))
+                    .kind(CommandOptionType::Integer)
+                    .min_int_value(1)
+                    .max_int_value(MAX_LOG_LINES)
+                    .required(false);
+
+                let mut since = CreateApplicationCommandOption::default();
+                since
+                    .name(Self::SINCE_OPTION)
+                    .description("Only fetch lines logged within this duration, e.g. 10m or 2h")
+                    .kind(CommandOptionType::String)
+                    .required(false);
+
+                let mut follow = CreateApplicationCommandOption::default();
+                follow
+                    .name(Self::FOLLOW_OPTION)
+                    .description("Stream new logs into a thread")
+                    .kind(CommandOptionType::Boolean)
+                    .required(false);
+
+                let mut filter = CreateApplicationCommandOption::default();
+                filter
+                    .name(Self::FILTER_OPTION)
+                    .description("Only include lines matching this regex")
+                    .kind(CommandOptionType::String)
+                    .required(false);
+
+                let mut timeout = CreateApplicationCommandOption::default();
+                timeout
+                    .name(Self::TIMEOUT_OPTION)
+                    .description(format!(
+                        "How long to stream logs for (default {})",
+                        humantime::format_duration(DEFAULT_FOLLOW_TIMEOUT)
+                    ))
+                    .kind(CommandOptionType::String)
+                    .required(false);
+
+                options.push(lines);
+                options.push(since);
+                options.push(follow);
+                options.push(filter);
+                options.push(timeout);
+            }
+
+            if matches!(self, SlashCommand::Backup) {
+                let server = options.remove(0);
+
+                let mut create = CreateApplicationCommandOption::default();
+                create
+                    .name(Self::BACKUP_CREATE)
+                    .description("Archives the current server data")
+                    .kind(CommandOptionType::SubCommand)
+                    .add_sub_option(server.clone());
+
+                let mut list = CreateApplicationCommandOption::default();
+                list.name(Self::BACKUP_LIST)
+                    .description("Lists the archives of the server data")
+                    .kind(CommandOptionType::SubCommand)
+                    .add_sub_option(server.clone());
+
+                let mut archive = CreateApplicationCommandOption::default();
+                archive
+                    .name(Self::ARCHIVE_OPTION)
+                    .description("The archive to restore, as shown by list")
+                    .kind(CommandOptionType::String)
+                    .required(true);
+
+                let mut confirm = CreateApplicationCommandOption::default();
+                confirm
+                    .name(Self::CONFIRM_OPTION)
+                    .description("Confirms the current server data should be replaced")
+                    .kind(CommandOptionType::Boolean)
+                    .required(false);
+
+                let mut restore = CreateApplicationCommandOption::default();
+                restore
+                    .name(Self::BACKUP_RESTORE)
+                    .description(
+                        "Replaces the server data with an archive; the server must be stopped",
+                    )
+                    .kind(CommandOptionType::SubCommand)
+                    .add_sub_option(server)
+                    .add_sub_option(archive)
+                    .add_sub_option(confirm);
+
+                options = vec![create, list, restore];
+            }
+
+            options
         }
     }
 
     pub async fn run(
         &self,
-        servers: &ServerMap,
+        data: &RwLock<TypeMap>,
         guild_id: GuildId,
         options: &[CommandDataOption],
-    ) -> String {
-        println!("Running command '{:?}' for guild id {}", self, guild_id);
+    ) -> CommandReply {
+        debug!(command = %self, guild = guild_id.0, "Running command");
+
+        let servers = data.read().await.get::<ServerKey>().unwrap().clone();
 
         if matches!(self, SlashCommand::List) {
             {
                 let mut res = String::new();
 
-                let servers = servers
+                let guild_servers = servers
                     .iter()
                     .filter(|s| s.1.get_guild_ids().contains(&guild_id))
-                    .map(|s| s.0)
                     .collect::<Vec<_>>();
+                let servers = guild_servers.iter().map(|s| s.0).collect::<Vec<_>>();
 
                 match servers.len() {
                     0 => res.push_str("No servers available"),
                     1 => {
                         res.push_str("Available server: ");
-                        res.push_str(&servers[0]);
+                        res.push_str(servers[0]);
                     }
                     _ => {
                         res.push_str("Available servers: ");
@@ -144,46 +354,183 @@ impl SlashCommand {
                     }
                 }
 
-                res
+                let mut host_names = guild_servers
+                    .iter()
+                    .filter_map(|(_, server)| server.get_host())
+                    .collect::<Vec<_>>();
+                host_names.sort();
+                host_names.dedup();
+
+                if !host_names.is_empty() {
+                    let hosts = data.read().await.get::<HostsKey>().unwrap().clone();
+                    res.push_str(&Self::host_health(&hosts, &host_names).await);
+                }
+
+                res.into()
             }
+        } else if matches!(self, SlashCommand::HostStatus) {
+            Self::run_host_status(&servers, guild_id).await
         } else {
-            let server_name = match options.get(0) {
-                Some(&CommandDataOption {
-                    name: ref cmd_name,
-                    value: Some(Value::String(ref server_name)),
-                    ..
-                }) if *cmd_name == *Self::SERVER_OPTION => server_name.trim(),
-                _ => return "No server specified".to_string(),
+            let server_name = match Self::server_name(options) {
+                Some(server_name) => server_name,
+                None => return CommandReply::error("No server specified"),
             };
 
-            let server = servers.get(server_name);
-            match server {
-                Some(server) => {
-                    if server.get_guild_ids().contains(&guild_id) {
-                        self.run_with_server(server_name, server).await
-                    } else {
-                        format!("Server {server_name} not found")
+            self.run_for_server(data, guild_id, server_name, options)
+                .await
+        }
+    }
+
+    /// Runs a command on a single server, if the server is available in the guild
+    pub async fn run_for_server(
+        &self,
+        data: &RwLock<TypeMap>,
+        guild_id: GuildId,
+        server_name: &str,
+        options: &[CommandDataOption],
+    ) -> CommandReply {
+        let servers = data.read().await.get::<ServerKey>().unwrap().clone();
+
+        let server = match servers.get(server_name) {
+            Some(server) if server.get_guild_ids().contains(&guild_id) => server,
+            _ => return CommandReply::error(format!("Server {server_name} not found")),
+        };
+
+        if matches!(self, SlashCommand::History) {
+            return Self::run_history(data, server_name).await;
+        }
+
+        // held until the command finishes, so backups or updates of the server never overlap
+        let mut _backup_guard = None;
+        let mut _update_guard = None;
+
+        {
+            let data = data.read().await;
+            admission::record_activity(&data, server_name);
+
+            if matches!(self, SlashCommand::Backup) {
+                match backup::begin_backup(&data, server_name) {
+                    Some(guard) => _backup_guard = Some(guard),
+                    None => {
+                        return CommandReply::error(format!(
+                            "A backup of server {} is already in progress",
+                            server_name
+                        ))
+                    }
+                }
+            }
+
+            if matches!(self, SlashCommand::Update) {
+                match update::begin_update(&data, server_name) {
+                    Some(guard) => _update_guard = Some(guard),
+                    None => {
+                        return CommandReply::error(format!(
+                            "An update of server {} is already in progress",
+                            server_name
+                        ))
                     }
                 }
-                None => format!("Server {server_name} not found"),
             }
         }
+
+        // checks capacity, then wakes the server's host if it's asleep
+        if matches!(
+            self,
+            SlashCommand::Start | SlashCommand::Restart | SlashCommand::Resume
+        ) {
+            if let Err(err) = admission::admit(data, guild_id, server_name, *self).await {
+                return admission::reply(server_name, err);
+            }
+        }
+
+        // the caller checks the status with its actor afterwards, which clears the mark
+        if self.changes_status() {
+            notifications::expect_change(&*data.read().await, server_name);
+        }
+
+        let mut reply = self.run_with_server(server_name, server, options).await;
+
+        if matches!(self, SlashCommand::Stop) && !reply.failed {
+            backup::backup_on_stop(&*data.read().await, server_name);
+        }
+
+        if matches!(self, SlashCommand::Connect) && !reply.failed {
+            join::add_join_link(data, server_name, server, &mut reply).await;
+        }
+
+        reply
     }
 
-    async fn run_with_server(&self, server_name: &str, server: &ServerType) -> String {
-        match self {
+    /// Gets the server the command was invoked for, if any
+    pub fn server_name(options: &[CommandDataOption]) -> Option<&str> {
+        match Self::option(options, Self::SERVER_OPTION) {
+            Some(Value::String(server_name)) => Some(server_name.trim()),
+            _ => None,
+        }
+    }
+
+    fn option<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a Value> {
+        options.iter().find_map(|option| {
+            if option.kind == CommandOptionType::SubCommand {
+                Self::option(&option.options, name)
+            } else if option.name == name {
+                option.value.as_ref()
+            } else {
+                None
+            }
+        })
+    }
+
+    fn endpoint(options: &[CommandDataOption]) -> Option<&str> {
+        match Self::option(options, Self::ENDPOINT_OPTION) {
+            Some(Value::String(endpoint)) => Some(endpoint.trim()),
+            _ => None,
+        }
+    }
+
+    fn subcommand(options: &[CommandDataOption]) -> Option<&str> {
+        options
+            .iter()
+            .find(|option| option.kind == CommandOptionType::SubCommand)
+            .map(|option| option.name.as_str())
+    }
+
+    /// Returns true if the command can change the status of the server
+    pub fn changes_status(&self) -> bool {
+        matches!(
+            self,
+            SlashCommand::Backup
+                | SlashCommand::Update
+                | SlashCommand::Start
+                | SlashCommand::Stop
+                | SlashCommand::Restart
+                | SlashCommand::Pause
+                | SlashCommand::Unpause
+                | SlashCommand::Resume
+        )
+    }
+
+    async fn run_with_server(
+        &self,
+        server_name: &str,
+        server: &ServerType,
+        options: &[CommandDataOption],
+    ) -> CommandReply {
+        let content = match self {
             // Server Management
-            SlashCommand::Connect => match server.connect().await {
-                Ok(connect_string) => connect_string,
-                Err(err) => err.to_string(),
-            },
+            SlashCommand::Connect => {
+                match server.connect(server_name, Self::endpoint(options)).await {
+                    Ok(connect_string) => connect_string,
+                    Err(err) => return CommandReply::error(err),
+                }
+            }
             SlashCommand::Start => match server.start_server() {
                 Ok(_) => format!(
                     "Started server {} -- status: {:?}",
                     server_name,
                     server.get_status()
                 ),
-                Err(err) => err.to_string(),
+                Err(err) => return CommandReply::error(err),
             },
             SlashCommand::Stop => match server.stop_server() {
                 Ok(_) => format!(
@@ -191,7 +538,7 @@ impl SlashCommand {
                     server_name,
                     server.get_status()
                 ),
-                Err(err) => err.to_string(),
+                Err(err) => return CommandReply::error(err),
             },
             SlashCommand::Restart => match server.restart_server() {
                 Ok(_) => format!(
@@ -199,7 +546,7 @@ impl SlashCommand {
                     server_name,
                     server.get_status()
                 ),
-                Err(err) => err.to_string(),
+                Err(err) => return CommandReply::error(err),
             },
             SlashCommand::Pause => match server.pause_server() {
                 Ok(_) => format!(
@@ -207,7 +554,7 @@ impl SlashCommand {
                     server_name,
                     server.get_status()
                 ),
-                Err(err) => err.to_string(),
+                Err(err) => return CommandReply::error(err),
             },
             SlashCommand::Unpause => match server.unpause_server() {
                 Ok(_) => format!(
@@ -215,7 +562,7 @@ impl SlashCommand {
                     server_name,
                     server.get_status()
                 ),
-                Err(err) => err.to_string(),
+                Err(err) => return CommandReply::error(err),
             },
             SlashCommand::Resume => match server.resume_server() {
                 Ok(_) => format!(
@@ -223,14 +570,349 @@ impl SlashCommand {
                     server_name,
                     server.get_status()
                 ),
-                Err(err) => err.to_string(),
+                Err(err) => return CommandReply::error(err),
             },
             SlashCommand::Status => match server.get_status() {
+                Ok(ServerStatus::Running) => {
+                    let mut content =
+                        format!("Server {} status: {:?}", server_name, ServerStatus::Running);
+
+                    if let Ok(usage) = server.get_resource_usage().await {
+                        content.push_str(&format!("\n{}", usage));
+                    }
+
+                    match server.get_ports().await {
+                        Ok(ports) if !ports.is_empty() => content.push_str(&format!(
+                            "\nPorts: {}",
+                            ports
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )),
+                        _ => {}
+                    }
+
+                    content
+                }
                 Ok(status) => format!("Server {} status: {:?}", server_name, status),
-                Err(err) => err.to_string(),
+                Err(err) => return CommandReply::error(err),
+            },
+            SlashCommand::Update => match server.update_server().await {
+                Ok(update) => format!("Server {} {}", server_name, update),
+                Err(err) => return CommandReply::error(err),
+            },
+            SlashCommand::Logs => return Self::run_logs(server_name, server, options),
+            SlashCommand::Backup => return Self::run_backup(server_name, server, options).await,
+            SlashCommand::List | SlashCommand::HostStatus | SlashCommand::History => {
+                unreachable!()
+            }
+        };
+
+        content.into()
+    }
+
+    async fn run_backup(
+        server_name: &str,
+        server: &ServerType,
+        options: &[CommandDataOption],
+    ) -> CommandReply {
+        let config = match server.get_backup() {
+            Some(config) => config,
+            None => {
+                return CommandReply::error(format!(
+                    "Backups are not configured for server {}",
+                    server_name
+                ))
+            }
+        };
+
+        let content = match Self::subcommand(options) {
+            Some(Self::BACKUP_CREATE) => match backup::create_backup(server_name, server, config)
+                .await
+            {
+                Ok(summary) => {
+                    let mut res = format!(
+                        "Created backup {} ({}) of server {} in {}",
+                        summary.backup.name,
+                        stats::format_bytes(summary.backup.size),
+                        server_name,
+                        humantime::format_duration(Duration::from_secs(summary.duration.as_secs()))
+                    );
+
+                    if !summary.pruned.is_empty() {
+                        res.push_str(&format!(
+                            "\nRemoved old backups: {}",
+                            summary.pruned.join(", ")
+                        ));
+                    }
+
+                    res
+                }
+                Err(err) => return CommandReply::error(err),
             },
-            SlashCommand::List => unreachable!(),
-            // Role Management
+            Some(Self::BACKUP_LIST) => match backup::list_backups(server_name, config) {
+                Ok(backups) if backups.is_empty() => {
+                    format!("No backups found for server {}", server_name)
+                }
+                Ok(backups) => {
+                    let mut res = format!("Backups of server {}:", server_name);
+
+                    for backup in backups {
+                        let line = format!(
+                            "\n- `{}` ({})",
+                            backup.name,
+                            stats::format_bytes(backup.size)
+                        );
+
+                        if res.chars().count() + line.chars().count() > MAX_MESSAGE_LENGTH {
+                            break;
+                        }
+                        res.push_str(&line);
+                    }
+
+                    res
+                }
+                Err(err) => return CommandReply::error(format!("Failed to list backups: {}", err)),
+            },
+            Some(Self::BACKUP_RESTORE) => {
+                let archive = match Self::option(options, Self::ARCHIVE_OPTION) {
+                    Some(Value::String(archive)) => archive.trim(),
+                    _ => return CommandReply::error("No archive specified"),
+                };
+
+                match Self::option(options, Self::CONFIRM_OPTION) {
+                    Some(Value::Bool(true)) => {
+                        match backup::restore_backup(server_name, server, config, archive).await {
+                            Ok(previous) => format!(
+                                "Restored server {} from {}; the previous data was saved as {}",
+                                server_name, archive, previous.name
+                            ),
+                            Err(err) => return CommandReply::error(err),
+                        }
+                    }
+                    _ => format!(
+                        "Restoring {} will replace the current data of server {}. Run the command again with confirm:true to proceed.",
+                        archive, server_name
+                    ),
+                }
+            }
+            _ => return CommandReply::error("No backup command specified"),
+        };
+
+        content.into()
+    }
+
+    async fn run_history(data: &RwLock<TypeMap>, server_name: &str) -> CommandReply {
+        const HISTORY_ENTRIES: usize = 20;
+
+        let store = data.read().await.get::<StoreKey>().unwrap().clone();
+
+        match store.audit_entries(Some(server_name), None, HISTORY_ENTRIES) {
+            Ok(entries) if entries.is_empty() => {
+                format!("No actions recorded for server {}", server_name).into()
+            }
+            Ok(entries) => {
+                let mut res = format!("Recent actions on server {}:", server_name);
+
+                for entry in entries {
+                    let line = format!("\n- {}", entry);
+
+                    if res.chars().count() + line.chars().count() > MAX_MESSAGE_LENGTH {
+                        break;
+                    }
+                    res.push_str(&line);
+                }
+
+                res.into()
+            }
+            Err(err) => CommandReply::error(format!("Failed to read server history: {}", err)),
+        }
+    }
+
+    async fn run_host_status(servers: &Arc<ServerMap>, guild_id: GuildId) -> CommandReply {
+        let mut res = match HostUsage::read() {
+            Ok(usage) => format!("Host: {}", usage),
+            Err(err) => format!("Host: failed to read usage: {}", err),
+        };
+
+        let all_servers = servers;
+        let mut servers = all_servers
+            .iter()
+            .filter(|s| s.1.get_guild_ids().contains(&guild_id))
+            .collect::<Vec<_>>();
+        servers.sort_by_key(|s| s.0);
+
+        // docker stats takes a moment per container, so every server is queried at once, with
+        // each status read on its own blocking thread
+        let usages = futures::future::join_all(servers.iter().map(|(name, server)| {
+            let status = server_commands::status_of(all_servers.clone(), name.to_string());
+
+            async move {
+                match status.await {
+                    Ok(ServerStatus::Running) => match server.get_resource_usage().await {
+                        Ok(usage) => format!("{:?} | {}", ServerStatus::Running, usage),
+                        Err(err) => format!("{:?} | {}", ServerStatus::Running, err),
+                    },
+                    Ok(status) => format!("{:?}", status),
+                    Err(err) => err.to_string(),
+                }
+            }
+        }))
+        .await;
+
+        for ((name, _), usage) in servers.iter().zip(usages) {
+            res.push_str(&format!("\n**{}**: {}", name, usage));
+        }
+
+        if res.chars().count() <= MAX_MESSAGE_LENGTH {
+            res.into()
+        } else {
+            CommandReply {
+                content: format!(
+                    "Usage of the host and {} servers is attached",
+                    servers.len()
+                ),
+                attachments: vec![(
+                    "host-status.txt".to_string(),
+                    res.replace("**", "").into_bytes(),
+                )],
+                ..Default::default()
+            }
+        }
+    }
+
+    /// Describes whether each host can be reached, checking them all at once
+    async fn host_health(hosts: &HostMap, host_names: &[&str]) -> String {
+        let health = host_names.iter().map(|host_name| async move {
+            match hosts.get(*host_name) {
+                Some(host) => power::health(host).await,
+                None => Err("not configured".to_string()),
+            }
+        });
+        let health = futures::future::join_all(health).await;
+
+        let mut res = String::from("\nHosts:");
+        for (host_name, health) in host_names.iter().zip(health) {
+            match health {
+                Ok(health) => res.push_str(&format!("\n**{}**: {}", host_name, health)),
+                Err(err) => res.push_str(&format!("\n**{}**: unreachable ({})", host_name, err)),
+            }
+        }
+
+        res
+    }
+
+    fn run_logs(
+        server_name: &str,
+        server: &ServerType,
+        options: &[CommandDataOption],
+    ) -> CommandReply {
+        let lines = match Self::option(options, Self::LINES_OPTION) {
+            Some(Value::Number(lines)) => lines
+                .as_u64()
+                .map_or(DEFAULT_LOG_LINES, |lines| lines as usize)
+                .min(MAX_LOG_LINES),
+            _ => DEFAULT_LOG_LINES,
+        };
+
+        let since = match Self::option(options, Self::SINCE_OPTION) {
+            Some(Value::String(since)) => match humantime::parse_duration(since.trim()) {
+                Ok(since) => Some(since),
+                Err(err) => {
+                    return CommandReply::error(format!("Invalid duration '{}': {}", since, err))
+                }
+            },
+            _ => None,
+        };
+
+        let filter = match Self::option(options, Self::FILTER_OPTION) {
+            Some(Value::String(filter)) => match Regex::new(filter) {
+                Ok(filter) => Some(filter),
+                Err(err) => {
+                    return CommandReply::error(format!("Invalid filter '{}': {}", filter, err))
+                }
+            },
+            _ => None,
+        };
+
+        if let Some(Value::Bool(true)) = Self::option(options, Self::FOLLOW_OPTION) {
+            let timeout = match Self::option(options, Self::TIMEOUT_OPTION) {
+                Some(Value::String(timeout)) => match humantime::parse_duration(timeout.trim()) {
+                    Ok(timeout) => timeout.min(MAX_FOLLOW_TIMEOUT),
+                    Err(err) => {
+                        return CommandReply::error(format!(
+                            "Invalid duration '{}': {}",
+                            timeout, err
+                        ))
+                    }
+                },
+                _ => DEFAULT_FOLLOW_TIMEOUT,
+            };
+
+            let redactions = match logs::compile_redactions(server.get_log_redactions()) {
+                Ok(redactions) => redactions,
+                Err(err) => {
+                    return CommandReply::error(format!("Invalid log redaction pattern: {}", err))
+                }
+            };
+
+            return match server.follow_logs() {
+                Ok(stream) => CommandReply {
+                    content: format!(
+                        "Following logs for server {} for {}",
+                        server_name,
+                        humantime::format_duration(timeout)
+                    ),
+                    follow: Some(LogFollow {
+                        server_name: server_name.to_string(),
+                        stream,
+                        filter,
+                        redactions,
+                        timeout,
+                    }),
+                    ..Default::default()
+                },
+                Err(err) => CommandReply::error(err),
+            };
+        }
+
+        let logs = match server.get_logs(&LogQuery { lines, since }) {
+            Ok(logs) => logs,
+            Err(err) => return CommandReply::error(err),
+        };
+
+        let logs = match filter {
+            Some(filter) => logs
+                .lines()
+                .filter(|line| filter.is_match(line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => logs,
+        };
+
+        if logs.trim().is_empty() {
+            return format!("No logs found for server {}", server_name).into();
+        }
+
+        let content = format!(
+            "Logs for server {}:\n```\n{}\n```",
+            server_name,
+            logs.replace("```", "'''")
+        );
+
+        if content.chars().count() <= MAX_MESSAGE_LENGTH {
+            content.into()
+        } else {
+            CommandReply {
+                content: format!(
+                    "Logs for server {} ({} lines) are attached",
+                    server_name,
+                    logs.lines().count()
+                ),
+                attachments: vec![(format!("{}-logs.txt", server_name), logs.into_bytes())],
+                ..Default::default()
+            }
         }
     }
 
@@ -245,6 +927,11 @@ impl SlashCommand {
             SlashCommand::Resume => "Resuming server...".to_string(),
             SlashCommand::Status => "Getting server status...".to_string(),
             SlashCommand::List => "Getting available server list...".to_string(),
+            SlashCommand::Logs => "Getting server logs...".to_string(),
+            SlashCommand::HostStatus => "Getting host status...".to_string(),
+            SlashCommand::Backup => "Managing server backups...".to_string(),
+            SlashCommand::Update => "Updating server...".to_string(),
+            SlashCommand::History => "Getting server history...".to_string(),
         }
     }
 }
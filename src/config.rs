@@ -1,6 +1,15 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::OnceLock,
+};
 
 use serde::{Deserialize, Serialize};
+
+use crate::template::{
+    ConnectVars, DockerConnectVars, NotificationVars, Template, Variables, VoiceVars,
+};
 use serenity::model::{
     id::{ChannelId, RoleId},
     prelude::GuildId,
@@ -8,25 +17,155 @@ use serenity::model::{
 
 pub type ServerMap = HashMap<String, ServerType>;
 pub type VoiceChannelConfigs = HashMap<RoleId, VoiceChatConfig>;
+pub type HostMap = HashMap<String, HostConfig>;
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub discord_token: String,
     pub servers: ServerMap,
     pub vcs: VoiceChannelConfigs,
+    // how often to poll server statuses for notifications, in seconds
+    #[serde(default = "default_status_poll_secs")]
+    pub status_poll_secs: u64,
+    // limits on how many servers can run at once
+    #[serde(default)]
+    pub admission: AdmissionConfig,
+    // sqlite database to keep runtime state in across restarts
+    #[serde(default = "default_state_path")]
+    pub state_path: PathBuf,
+    // text channel to post every server management action in
+    #[serde(default)]
+    pub audit_channel_id: Option<ChannelId>,
+    // how the bot's own logs are written
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    // http server for metrics, the control api and the dashboard; disabled if not set
+    #[serde(default)]
+    pub web: Option<WebConfig>,
+    // endpoints to POST server and voice events to
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    // where $PUBLIC_IP in connect strings comes from
+    #[serde(default)]
+    pub public_ip: PublicIpConfig,
+    // machines servers run on that can be woken and suspended, referenced by the servers' host
+    #[serde(default)]
+    pub hosts: HostMap,
 }
 
+impl Config {
+    /// Points docker servers at the engine of the host they run on.
+    /// Fails if a server references a host that isn't configured, rather than running its
+    /// commands somewhere else.
+    pub fn resolve_docker_engines(&mut self) -> Result<(), String> {
+        for (server_name, server) in self.servers.iter_mut() {
+            let host = match server.get_host() {
+                Some(host_name) => self.hosts.get(host_name).ok_or_else(|| {
+                    format!(
+                        "Server {} references unknown host {}",
+                        server_name, host_name
+                    )
+                })?,
+                None => continue,
+            };
+
+            if let ServerType::Docker(docker) = server {
+                docker.engine = host.docker.clone();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn default_status_poll_secs() -> u64 {
+    30
+}
+
+fn default_state_path() -> PathBuf {
+    PathBuf::from("./assets/state.db")
+}
+
+// servers are only loaded once, so the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Deserialize)]
 pub enum ServerType {
     Docker(Docker),
-    // Custom(CustomServer),
+    Custom(CustomServer),
+    Kubernetes(KubernetesServer),
+    Pterodactyl(PterodactylServer),
 }
 
 impl ServerType {
     pub fn get_guild_ids(&self) -> &HashSet<GuildId> {
         match self {
             ServerType::Docker(docker) => &docker.guild_ids,
-            // ServerType::Custom(custom) => &custom.guild_ids,
+            ServerType::Custom(custom) => &custom.guild_ids,
+            ServerType::Kubernetes(kubernetes) => &kubernetes.guild_ids,
+            ServerType::Pterodactyl(pterodactyl) => &pterodactyl.guild_ids,
+        }
+    }
+
+    pub fn get_notifications(&self) -> Option<&NotificationConfig> {
+        match self {
+            ServerType::Docker(docker) => docker.notifications.as_ref(),
+            ServerType::Custom(custom) => custom.notifications.as_ref(),
+            ServerType::Kubernetes(kubernetes) => kubernetes.notifications.as_ref(),
+            ServerType::Pterodactyl(pterodactyl) => pterodactyl.notifications.as_ref(),
+        }
+    }
+
+    pub fn get_memory_weight(&self) -> u64 {
+        match self {
+            ServerType::Docker(docker) => docker.memory_weight,
+            ServerType::Custom(custom) => custom.memory_weight,
+            ServerType::Kubernetes(kubernetes) => kubernetes.memory_weight,
+            ServerType::Pterodactyl(pterodactyl) => pterodactyl.memory_weight,
+        }
+    }
+
+    pub fn get_backup(&self) -> Option<&BackupConfig> {
+        match self {
+            ServerType::Docker(docker) => docker.backup.as_ref(),
+            ServerType::Custom(custom) => custom.backup.as_ref(),
+            // the server's data lives in the cluster's volumes or the panel's nodes, out of the bot's reach
+            ServerType::Kubernetes(_) | ServerType::Pterodactyl(_) => None,
+        }
+    }
+
+    pub fn get_query_address(&self) -> Option<&str> {
+        match self {
+            ServerType::Docker(docker) => docker.query_address.as_deref(),
+            ServerType::Custom(custom) => custom.query_address.as_deref(),
+            ServerType::Kubernetes(kubernetes) => kubernetes.query_address.as_deref(),
+            ServerType::Pterodactyl(pterodactyl) => pterodactyl.query_address.as_deref(),
+        }
+    }
+
+    pub fn get_host(&self) -> Option<&str> {
+        match self {
+            ServerType::Docker(docker) => docker.host.as_deref(),
+            ServerType::Custom(custom) => custom.host.as_deref(),
+            ServerType::Kubernetes(_) | ServerType::Pterodactyl(_) => None,
+        }
+    }
+
+    /// Name of the kind of server, for metrics
+    pub fn get_type_name(&self) -> &'static str {
+        match self {
+            ServerType::Docker(_) => "docker",
+            ServerType::Custom(_) => "custom",
+            ServerType::Kubernetes(_) => "kubernetes",
+            ServerType::Pterodactyl(_) => "pterodactyl",
+        }
+    }
+
+    pub fn get_log_redactions(&self) -> &[String] {
+        match self {
+            ServerType::Docker(docker) => &docker.log_redactions,
+            ServerType::Custom(custom) => &custom.log_redactions,
+            ServerType::Kubernetes(kubernetes) => &kubernetes.log_redactions,
+            ServerType::Pterodactyl(pterodactyl) => &pterodactyl.log_redactions,
         }
     }
 }
@@ -34,13 +173,39 @@ impl ServerType {
 #[derive(Serialize, Deserialize)]
 pub struct Docker {
     pub container_name: String,
-    pub connect: String,
+    // can use {public_ip}, {port} (or {port:<container port>}), {server_name} and {players}
+    pub connect: Connect<DockerConnectVars>,
     pub guild_ids: HashSet<GuildId>,
+    #[serde(default)]
+    pub notifications: Option<NotificationConfig>,
+    // regex patterns to redact from server logs
+    #[serde(default)]
+    pub log_redactions: Vec<String>,
+    // expected memory usage of the server in MiB, checked against free host memory before starting
+    #[serde(default)]
+    pub memory_weight: u64,
+    // where to back up server data from and to
+    #[serde(default)]
+    pub backup: Option<BackupConfig>,
+    // steam query address ("host:port") to read player counts from, shown on the dashboard
+    #[serde(default)]
+    pub query_address: Option<String>,
+    // link to join the server from, shown as a button on /connect-server
+    #[serde(default)]
+    pub join_link: Option<JoinLink<DockerConnectVars>>,
+    // name of the host the server runs on, woken up before the server starts; its docker engine is
+    // used if it has one
+    #[serde(default)]
+    pub host: Option<String>,
+    // the docker engine of the host, filled in after loading
+    #[serde(skip)]
+    pub engine: Option<DockerEngine>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CustomServer {
-    pub connect: String,
+    // can use {public_ip}, {server_name} and {players}
+    pub connect: Connect<ConnectVars>,
     pub start: CommandDefinition,
     pub stop: CommandDefinition,
     pub pause: Option<CommandDefinition>,
@@ -48,6 +213,130 @@ pub struct CustomServer {
     pub restart: Option<CommandDefinition>,
     pub status: StatusCommand,
     pub guild_ids: HashSet<GuildId>,
+    #[serde(default)]
+    pub notifications: Option<NotificationConfig>,
+    // where to read server logs from
+    #[serde(default)]
+    pub logs: Option<LogSource>,
+    // regex patterns to redact from server logs
+    #[serde(default)]
+    pub log_redactions: Vec<String>,
+    // systemd unit running the server, used to read resource usage from its cgroup
+    #[serde(default)]
+    pub systemd_unit: Option<String>,
+    // expected memory usage of the server in MiB, checked against free host memory before starting
+    #[serde(default)]
+    pub memory_weight: u64,
+    // where to back up server data from and to
+    #[serde(default)]
+    pub backup: Option<BackupConfig>,
+    // steam query address ("host:port") to read player counts from, shown on the dashboard
+    #[serde(default)]
+    pub query_address: Option<String>,
+    // link to join the server from, shown as a button on /connect-server
+    #[serde(default)]
+    pub join_link: Option<JoinLink<ConnectVars>>,
+    // command that updates the server to its newest version
+    #[serde(default)]
+    pub update: Option<CommandDefinition>,
+    // command that prints the installed version of the server, reported before and after updates
+    #[serde(default)]
+    pub version: Option<CommandDefinition>,
+    // name of the host the server runs on, woken up before the server starts
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct KubernetesServer {
+    // url of the cluster's api server, e.g. "https://192.168.1.40:6443"
+    pub api_url: String,
+    // file holding a bearer token allowed to scale, patch and read the workload and its pods.
+    // read before each request, so rotated service account tokens are picked up
+    pub token_file: PathBuf,
+    // pem certificate of the cluster's ca; the system's roots are trusted if not set
+    #[serde(default)]
+    pub ca_cert: Option<PathBuf>,
+    #[serde(default = "default_kubernetes_namespace")]
+    pub namespace: String,
+    // the workload running the server, scaled to 1 replica to start it and 0 to stop it
+    pub workload: KubernetesWorkload,
+    // can use {public_ip}, {server_name} and {players}
+    pub connect: Connect<ConnectVars>,
+    pub guild_ids: HashSet<GuildId>,
+    #[serde(default)]
+    pub notifications: Option<NotificationConfig>,
+    // regex patterns to redact from server logs
+    #[serde(default)]
+    pub log_redactions: Vec<String>,
+    // expected memory usage of the server in MiB, checked against free host memory before starting
+    #[serde(default)]
+    pub memory_weight: u64,
+    // steam query address ("host:port") to read player counts from, shown on the dashboard
+    #[serde(default)]
+    pub query_address: Option<String>,
+    // link to join the server from, shown as a button on /connect-server
+    #[serde(default)]
+    pub join_link: Option<JoinLink<ConnectVars>>,
+    // built on first use, from the ca certificate
+    #[serde(skip)]
+    pub client: OnceLock<Result<reqwest::Client, String>>,
+}
+
+fn default_kubernetes_namespace() -> String {
+    "default".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KubernetesWorkload {
+    Deployment(String),
+    StatefulSet(String),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PterodactylServer {
+    // url of the panel, e.g. "https://panel.example.com"
+    pub panel_url: String,
+    // the server's identifier, as shown in its url on the panel, e.g. "1a7ce997"
+    pub server_id: String,
+    // file holding a client api key ("ptlc_...") of an account with access to the server, e.g. a
+    // docker or systemd secret. read before each request, so the key never has to be in the config
+    pub api_key_file: PathBuf,
+    // seconds to wait for the server to stop before killing it
+    #[serde(default = "default_stop_timeout_secs")]
+    pub stop_timeout_secs: u64,
+    // can use {public_ip}, {server_name} and {players}
+    pub connect: Connect<ConnectVars>,
+    pub guild_ids: HashSet<GuildId>,
+    #[serde(default)]
+    pub notifications: Option<NotificationConfig>,
+    // regex patterns to redact from server logs
+    #[serde(default)]
+    pub log_redactions: Vec<String>,
+    // expected memory usage of the server in MiB, checked against free host memory before starting
+    #[serde(default)]
+    pub memory_weight: u64,
+    // steam query address ("host:port") to read player counts from, shown on the dashboard
+    #[serde(default)]
+    pub query_address: Option<String>,
+    // link to join the server from, shown as a button on /connect-server
+    #[serde(default)]
+    pub join_link: Option<JoinLink<ConnectVars>>,
+    // built on first use
+    #[serde(skip)]
+    pub client: OnceLock<Result<reqwest::Client, String>>,
+}
+
+fn default_stop_timeout_secs() -> u64 {
+    60
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum LogSource {
+    // path to a log file; the tail of the file is returned
+    File(PathBuf),
+    // command that prints the server logs; the tail of its output is returned
+    Command(CommandDefinition),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -81,6 +370,425 @@ pub struct VoiceChatConfig {
     pub voice_channel_ids: Vec<ChannelId>,
     pub text_channel_id: ChannelId,
     pub guild_ids: HashSet<GuildId>,
-    pub start_msgs: Vec<String>,
-    pub end_msgs: Vec<String>,
+    // messages can use {user}, {channel} and {role}; the role is pinged first unless {role} is used
+    pub start_msgs: Vec<Template<VoiceVars>>,
+    pub end_msgs: Vec<Template<VoiceVars>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    // the server data to archive
+    pub source: BackupSource,
+    // directory to store archives in
+    pub destination: PathBuf,
+    // number of archives to keep; older archives are deleted
+    #[serde(default = "default_backup_retention")]
+    pub retention: usize,
+    // zstd compression level, from 1 (fastest) to 22 (smallest)
+    #[serde(default = "default_compression_level")]
+    pub compression_level: i32,
+    // what to do with a running server while its data is archived
+    #[serde(default)]
+    pub quiesce: BackupQuiesce,
+    // back up the server data in the background whenever the bot stops the server
+    #[serde(default)]
+    pub backup_on_stop: bool,
+    // back up the server data on this interval while the server is running
+    #[serde(default)]
+    pub interval_mins: Option<u64>,
+}
+
+fn default_backup_retention() -> usize {
+    5
+}
+
+fn default_compression_level() -> i32 {
+    3
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BackupSource {
+    // a named docker volume
+    Volume(String),
+    // a directory on the host
+    Path(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum BackupQuiesce {
+    // archive the data while the server keeps running
+    #[default]
+    None,
+    // pause the server while archiving, then unpause it
+    Pause,
+    // stop the server while archiving, then start it again
+    Stop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebConfig {
+    // address to listen on, e.g. "0.0.0.0:8080"
+    pub listen: SocketAddr,
+    // serve prometheus metrics at /metrics
+    #[serde(default = "default_metrics")]
+    pub metrics: bool,
+    // clients of the server control api under /api; the api is disabled if empty
+    #[serde(default)]
+    pub api_tokens: Vec<ApiTokenConfig>,
+    // serve a live status page at /; connect strings and action results on it require an api token
+    #[serde(default)]
+    pub dashboard: bool,
+    // address the bot is reachable at, e.g. "https://bot.example.com"; discord buttons can only
+    // open web links, so other join links are sent through a redirect here
+    #[serde(default)]
+    pub public_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiTokenConfig {
+    // shown in the audit log for actions taken with this token
+    pub name: String,
+    // sent by the client as "Authorization: Bearer <token>"
+    pub token: String,
+    // the client can manage the servers available in these guilds, like a member of them
+    pub guild_ids: HashSet<GuildId>,
+}
+
+fn default_metrics() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicIpConfig {
+    // tried in order until one succeeds
+    #[serde(default = "default_ip_providers")]
+    pub providers: Vec<IpProviderConfig>,
+    // how long a resolved ip is reused for, in seconds
+    #[serde(default = "default_ip_ttl_secs")]
+    pub ttl_secs: u64,
+    // how long each provider gets to respond, in seconds
+    #[serde(default = "default_ip_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for PublicIpConfig {
+    fn default() -> Self {
+        Self {
+            providers: default_ip_providers(),
+            ttl_secs: default_ip_ttl_secs(),
+            timeout_secs: default_ip_timeout_secs(),
+        }
+    }
+}
+
+fn default_ip_providers() -> Vec<IpProviderConfig> {
+    vec![IpProviderConfig::Http("https://api.ipify.org".to_string())]
+}
+
+fn default_ip_ttl_secs() -> u64 {
+    300
+}
+
+fn default_ip_timeout_secs() -> u64 {
+    5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum IpProviderConfig {
+    // always this ip
+    Static(IpAddr),
+    // a url responding with the ip as plain text
+    Http(String),
+    // the address a host name resolves to, e.g. a dynamic dns name
+    Dns(String),
+    // the address of a local network interface, e.g. "eth0"
+    Interface(String),
+}
+
+/// A connect string, or a list of named ones for players on different networks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged, bound(deserialize = "V: Variables", serialize = ""))]
+pub enum Connect<V> {
+    Single(Template<V>),
+    Endpoints(Vec<ConnectEndpoint<V>>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "V: Variables", serialize = ""))]
+pub struct ConnectEndpoint<V> {
+    // shown before the connect string, and used to pick it with the endpoint option
+    pub name: String,
+    pub connect: Template<V>,
+}
+
+impl<V> Connect<V> {
+    /// Gets each connect string, with its name if it has one
+    pub fn endpoints(&self) -> Vec<(Option<&str>, &Template<V>)> {
+        match self {
+            Connect::Single(template) => vec![(None, template)],
+            Connect::Endpoints(endpoints) => endpoints
+                .iter()
+                .map(|endpoint| (Some(endpoint.name.as_str()), &endpoint.connect))
+                .collect(),
+        }
+    }
+}
+
+/// A link that opens the game and joins the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(deserialize = "V: Variables", serialize = ""))]
+pub enum JoinLink<V> {
+    // steam://connect/<address>/<password>, for games launched through steam
+    Steam {
+        // uses the same variables as the connect string, e.g. "{public_ip}:{port}"
+        address: Template<V>,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    // any other link, e.g. a game's own uri scheme
+    Uri(Template<V>),
+}
+
+impl<V> JoinLink<V> {
+    pub fn template(&self) -> &Template<V> {
+        match self {
+            JoinLink::Steam { address, .. } => address,
+            JoinLink::Uri(uri) => uri,
+        }
+    }
+
+    /// Builds the link from its rendered template
+    pub fn link(&self, rendered: &str) -> String {
+        match self {
+            JoinLink::Steam {
+                password: Some(password),
+                ..
+            } => format!("steam://connect/{}/{}", rendered, percent_encode(password)),
+            JoinLink::Steam { password: None, .. } => format!("steam://connect/{}", rendered),
+            JoinLink::Uri(_) => rendered.to_string(),
+        }
+    }
+}
+
+pub fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    // key to sign each body with, sent as "X-Signature-256: sha256=<hex hmac>"
+    #[serde(default)]
+    pub secret: Option<String>,
+    // events to send; every event is sent if empty
+    #[serde(default)]
+    pub events: HashSet<WebhookEventKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WebhookEventKind {
+    // a server changed status
+    StatusChanged,
+    // a server stopped without anyone stopping it
+    Crashed,
+    // a voice channel group got its first member
+    VoiceSessionStarted,
+    // the last member left a voice channel group
+    VoiceSessionEnded,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+    // tracing filter directives, e.g. "info,simple_dedicated_server_bot=debug"; RUST_LOG takes precedence
+    #[serde(default)]
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum LogFormat {
+    // multi-line, human readable output
+    #[default]
+    Pretty,
+    // single-line, human readable output
+    Compact,
+    // one json object per line, including the current span fields
+    Json,
+    // native journald entries, with span fields as journal fields
+    Journald,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HostConfig {
+    // docker engine on the host, used by the docker servers running on it instead of the local one
+    #[serde(default)]
+    pub docker: Option<DockerEngine>,
+    // mac address of the host's network interface, e.g. "aa:bb:cc:dd:ee:ff"; the host can't be woken if not set
+    #[serde(default)]
+    pub mac_address: Option<MacAddress>,
+    // where to send the wake-on-lan packet; the broadcast address of the host's network
+    #[serde(default = "default_wake_address")]
+    pub wake_address: SocketAddr,
+    // tcp address ("host:port") that accepts connections while the host is awake, e.g. its ssh port.
+    // if not set, the host is awake when its docker engine responds
+    #[serde(default)]
+    pub probe_address: Option<String>,
+    // how long to wait for the host to wake up before giving up, in seconds
+    #[serde(default = "default_wake_timeout_secs")]
+    pub wake_timeout_secs: u64,
+    // suspends the host once all of its servers have been stopped for a while
+    #[serde(default)]
+    pub suspend: Option<SuspendConfig>,
+}
+
+fn default_wake_address() -> SocketAddr {
+    SocketAddr::from(([255, 255, 255, 255], 9))
+}
+
+fn default_wake_timeout_secs() -> u64 {
+    120
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuspendConfig {
+    // minutes every server on the host must have been stopped and unused for
+    pub idle_mins: u64,
+    // command that suspends the host, e.g. ssh host systemctl suspend
+    pub command: CommandDefinition,
+}
+
+/// A remote docker engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DockerEngine {
+    // an engine listening on tcp, e.g. "192.168.1.20:2376"
+    Tcp {
+        address: String,
+        // client certificates, required by engines listening with --tlsverify
+        #[serde(default)]
+        tls: Option<DockerTls>,
+    },
+    // an engine reached over ssh, e.g. "user@192.168.1.20"; uses the ssh client's keys and config
+    Ssh(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DockerTls {
+    pub ca_cert: PathBuf,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl DockerEngine {
+    /// Global docker cli arguments that point it at the engine
+    pub fn cli_args(&self) -> Vec<String> {
+        match self {
+            DockerEngine::Tcp { address, tls } => {
+                let mut args = vec!["--host".to_string(), format!("tcp://{}", address)];
+
+                if let Some(tls) = tls {
+                    args.push("--tlsverify".to_string());
+                    for (flag, path) in [
+                        ("--tlscacert", &tls.ca_cert),
+                        ("--tlscert", &tls.cert),
+                        ("--tlskey", &tls.key),
+                    ] {
+                        args.push(flag.to_string());
+                        args.push(path.to_string_lossy().into_owned());
+                    }
+                }
+
+                args
+            }
+            DockerEngine::Ssh(destination) => {
+                vec!["--host".to_string(), format!("ssh://{}", destination)]
+            }
+        }
+    }
+}
+
+/// A mac address, written as six hex bytes separated by ':' or '-'
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MacAddress(pub [u8; 6]);
+
+impl TryFrom<String> for MacAddress {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid mac address {:?}", value);
+
+        let bytes = value
+            .split([':', '-'])
+            .map(|byte| match byte.len() {
+                2 => u8::from_str_radix(byte, 16).map_err(|_| invalid()),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        bytes.try_into().map(MacAddress).map_err(|_| invalid())
+    }
+}
+
+impl From<MacAddress> for String {
+    fn from(mac: MacAddress) -> Self {
+        mac.0
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdmissionConfig {
+    // maximum number of servers that can be running (or paused) at once
+    #[serde(default)]
+    pub max_running: Option<usize>,
+    // free host memory in MiB that must remain after accounting for the memory weight of a starting server
+    #[serde(default)]
+    pub min_free_memory_mib: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    // text channel to announce status changes in
+    pub channel_id: ChannelId,
+    // role to ping with each announcement
+    #[serde(default)]
+    pub role_id: Option<RoleId>,
+    // messages for each new status; can use {server_name}, {status}, {previous_status}, {user},
+    // {role} and {players}. If empty, no announcement will occur for that status.
+    #[serde(default = "default_running_msg")]
+    pub running_msg: Template<NotificationVars>,
+    #[serde(default = "default_stopped_msg")]
+    pub stopped_msg: Template<NotificationVars>,
+    #[serde(default = "default_paused_msg")]
+    pub paused_msg: Template<NotificationVars>,
+    #[serde(default = "default_unknown_msg")]
+    pub unknown_msg: Template<NotificationVars>,
+}
+
+fn default_running_msg() -> Template<NotificationVars> {
+    Template::parse("Server {server_name} is now running.").unwrap()
+}
+
+fn default_stopped_msg() -> Template<NotificationVars> {
+    Template::parse("Server {server_name} has stopped.").unwrap()
+}
+
+fn default_paused_msg() -> Template<NotificationVars> {
+    Template::parse("Server {server_name} has been paused.").unwrap()
+}
+
+fn default_unknown_msg() -> Template<NotificationVars> {
+    Template::parse("Server {server_name} is in an unknown state: {status}").unwrap()
 }
@@ -0,0 +1,271 @@
+use std::{
+    cmp::Reverse,
+    convert::Infallible,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::State,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse,
+    },
+    routing::get,
+    Router,
+};
+use futures::{future, stream, Stream, StreamExt};
+use serde::Serialize;
+use serenity::{
+    cache::Cache,
+    model::id::GuildId,
+    prelude::{RwLock, TypeMap},
+};
+use tokio::sync::watch;
+use tracing::{debug, error};
+
+use crate::{
+    audit::{AuditActor, AuditEntry},
+    notifications::ServerStatusKey,
+    query::{self, PlayerCount},
+    server_commands::ServerStatus,
+    store::StoreKey,
+    ServerKey, VoiceChatConfigKey, VoiceChatStateKey,
+};
+
+const PAGE: &str = include_str!("dashboard.html");
+
+// how often the shown state is collected; clients are only sent snapshots that changed
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+const RECENT_ACTIONS: usize = 15;
+
+/// Everything shown on the dashboard
+#[derive(Serialize)]
+struct Snapshot {
+    servers: Vec<ServerView>,
+    voice: Vec<VoiceView>,
+    actions: Vec<ActionView>,
+    // whether connect strings and action results can be fetched from the api with a token
+    api: bool,
+}
+
+#[derive(Serialize)]
+struct ServerView {
+    name: String,
+    // last status seen by the status watcher, if it has checked the server yet
+    status: Option<String>,
+    players: Option<PlayerCount>,
+}
+
+#[derive(Serialize)]
+struct VoiceView {
+    channels: Vec<String>,
+    members: usize,
+}
+
+#[derive(Serialize)]
+pub struct ActionView {
+    time: u64,
+    actor: String,
+    command: String,
+    server: Option<String>,
+    // only sent to api clients, since results can include connect strings and server details
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<String>,
+}
+
+#[derive(Clone)]
+struct DashboardState {
+    snapshots: watch::Receiver<Arc<String>>,
+}
+
+/// Routes for the dashboard page and its event stream, kept up to date by a background task
+pub fn router(data: Arc<RwLock<TypeMap>>, cache: Arc<Cache>, api: bool) -> Router {
+    let (sender, snapshots) = watch::channel(Arc::new(String::new()));
+    tokio::spawn(publish_snapshots(data, cache, api, sender));
+
+    Router::new()
+        .route("/", get(page))
+        .route("/dashboard/events", get(events))
+        .with_state(DashboardState { snapshots })
+}
+
+async fn page() -> Html<&'static str> {
+    Html(PAGE)
+}
+
+async fn events(State(state): State<DashboardState>) -> impl IntoResponse {
+    Sse::new(snapshot_events(state.snapshots)).keep_alive(KeepAlive::default())
+}
+
+// sends the current snapshot, then every new one
+fn snapshot_events(
+    snapshots: watch::Receiver<Arc<String>>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold((snapshots, true), |(mut snapshots, first)| async move {
+        if !first && snapshots.changed().await.is_err() {
+            return None;
+        }
+
+        let snapshot = snapshots.borrow_and_update().clone();
+        Some((snapshot, (snapshots, false)))
+    })
+    .filter(|snapshot| future::ready(!snapshot.is_empty()))
+    .map(|snapshot| Ok(Event::default().data(snapshot.as_str())))
+}
+
+async fn publish_snapshots(
+    data: Arc<RwLock<TypeMap>>,
+    cache: Arc<Cache>,
+    api: bool,
+    sender: watch::Sender<Arc<String>>,
+) {
+    loop {
+        let snapshot = collect_snapshot(&data, &cache, api).await;
+
+        match serde_json::to_string(&snapshot) {
+            Ok(snapshot) => {
+                sender.send_if_modified(|current| {
+                    if **current == snapshot {
+                        return false;
+                    }
+
+                    debug!("Publishing dashboard snapshot");
+                    *current = Arc::new(snapshot);
+                    true
+                });
+            }
+            Err(err) => error!(%err, "Failed to serialize dashboard snapshot"),
+        }
+
+        tokio::time::sleep(REFRESH_INTERVAL).await;
+    }
+}
+
+async fn collect_snapshot(data: &RwLock<TypeMap>, cache: &Cache, api: bool) -> Snapshot {
+    let (servers, statuses, voice, store) = {
+        let data = data.read().await;
+
+        let vc_configs = data.get::<VoiceChatConfigKey>().unwrap();
+        let vc_state = data.get::<VoiceChatStateKey>().unwrap();
+
+        let mut voice = vc_configs
+            .iter()
+            .map(|(role_id, config)| VoiceView {
+                channels: config
+                    .voice_channel_ids
+                    .iter()
+                    .map(|channel_id| match cache.guild_channel(*channel_id) {
+                        Some(channel) => channel.name,
+                        None => channel_id.0.to_string(),
+                    })
+                    .collect(),
+                members: vc_state.get(role_id).map_or(0, |members| members.len()),
+            })
+            .collect::<Vec<_>>();
+        voice.sort_by(|a, b| a.channels.cmp(&b.channels));
+
+        (
+            data.get::<ServerKey>().unwrap().clone(),
+            data.get::<ServerStatusKey>().unwrap().clone(),
+            voice,
+            data.get::<StoreKey>().unwrap().clone(),
+        )
+    };
+
+    let mut names = servers.keys().collect::<Vec<_>>();
+    names.sort();
+
+    let servers = future::join_all(names.into_iter().map(|name| {
+        let status = statuses.get(name);
+        let query_address = servers[name].get_query_address();
+
+        async move {
+            // stopped servers can't answer queries, so don't wait on them to time out
+            let players = match (status, query_address) {
+                (Some(ServerStatus::Running), Some(address)) => {
+                    match query::query_players(address).await {
+                        Ok(players) => Some(players),
+                        Err(err) => {
+                            debug!(server = %name, %err, "Failed to query players");
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+
+            ServerView {
+                name: name.clone(),
+                status: status.map(ToString::to_string),
+                players,
+            }
+        }
+    }))
+    .await;
+
+    let actions = match store.audit_entries(None, None, RECENT_ACTIONS) {
+        Ok(entries) => entries
+            .into_iter()
+            .map(|entry| action_view(entry, cache, false))
+            .collect(),
+        Err(err) => {
+            error!(%err, "Failed to read recent actions");
+            vec![]
+        }
+    };
+
+    Snapshot {
+        servers,
+        voice,
+        actions,
+        api,
+    }
+}
+
+/// The most recent actions taken in the guilds, with their results, for api clients
+pub async fn recent_actions(
+    data: &RwLock<TypeMap>,
+    cache: &Cache,
+    guild_ids: &[GuildId],
+) -> rusqlite::Result<Vec<ActionView>> {
+    let store = data.read().await.get::<StoreKey>().unwrap().clone();
+
+    let mut entries = vec![];
+    for guild_id in guild_ids {
+        entries.extend(store.audit_entries(None, Some(*guild_id), RECENT_ACTIONS)?);
+    }
+    entries.sort_by_key(|entry| Reverse(entry.time));
+    entries.truncate(RECENT_ACTIONS);
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| action_view(entry, cache, true))
+        .collect())
+}
+
+fn action_view(entry: AuditEntry, cache: &Cache, with_result: bool) -> ActionView {
+    let actor = match entry.actor {
+        AuditActor::User(user_id) => match cache.user(user_id) {
+            Some(user) => user.name,
+            None => user_id.0.to_string(),
+        },
+        AuditActor::Api(name) => format!("api: {}", name),
+    };
+
+    // the dashboard is public, so results are only shown to api clients
+    let result = with_result.then_some(entry.result);
+
+    ActionView {
+        time: entry
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        actor,
+        command: entry.command,
+        server: entry.server_name,
+        result,
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serenity::prelude::{RwLock, TypeMap, TypeMapKey};
+use sha2::Sha256;
+use tracing::warn;
+
+use crate::{
+    command::{CommandReply, ReplyLink},
+    config::{percent_encode, ServerType},
+    server_commands::ServerCommands,
+};
+
+// join buttons stay usable for a while after /connect-server, but can't be shared forever
+const REDIRECT_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+const JOIN_LABEL: &str = "Join";
+
+/// Redirects to join links through the bot's web server, if it has a public url
+pub struct JoinRedirectsKey;
+impl TypeMapKey for JoinRedirectsKey {
+    type Value = Option<Arc<JoinRedirects>>;
+}
+
+/// Signs redirect urls, so only people who ran /connect-server can get a server's join link
+pub struct JoinRedirects {
+    public_url: String,
+    // generated on startup, so redirects stop working when the bot restarts
+    key: [u8; 32],
+}
+
+impl JoinRedirects {
+    pub fn new(public_url: &str) -> Self {
+        let mut key = [0; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+
+        Self {
+            public_url: public_url.trim_end_matches('/').to_string(),
+            key,
+        }
+    }
+
+    fn url(&self, server_name: &str) -> String {
+        let expires = (SystemTime::now() + REDIRECT_TTL)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        format!(
+            "{}/join/{}?expires={}&signature={}",
+            self.public_url,
+            percent_encode(server_name),
+            expires,
+            hex::encode(self.mac(server_name, expires).finalize().into_bytes())
+        )
+    }
+
+    /// Returns true if the redirect was signed by this bot and hasn't expired
+    pub fn verify(&self, server_name: &str, expires: u64, signature: &str) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let signature = match hex::decode(signature) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+
+        expires >= now
+            && self
+                .mac(server_name, expires)
+                .verify_slice(&signature)
+                .is_ok()
+    }
+
+    fn mac(&self, server_name: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(format!("{}:{}", server_name, expires).as_bytes());
+        mac
+    }
+}
+
+/// Adds the server's join link to a /connect-server reply, as a button if discord can open it
+pub async fn add_join_link(
+    data: &RwLock<TypeMap>,
+    server_name: &str,
+    server: &ServerType,
+    reply: &mut CommandReply,
+) {
+    let link = match server.join_link(server_name).await {
+        Ok(Some(link)) => link,
+        Ok(None) => return,
+        Err(err) => {
+            warn!(server = %server_name, %err, "Failed to build join link");
+            return;
+        }
+    };
+
+    if link.starts_with("https://") || link.starts_with("http://") {
+        reply.links.push(ReplyLink {
+            label: JOIN_LABEL.to_string(),
+            url: link,
+        });
+        return;
+    }
+
+    let redirects = data.read().await.get::<JoinRedirectsKey>().unwrap().clone();
+
+    match redirects {
+        Some(redirects) => reply.links.push(ReplyLink {
+            label: JOIN_LABEL.to_string(),
+            url: redirects.url(server_name),
+        }),
+        None => reply.content.push_str(&format!("\n{}", link)),
+    }
+}
@@ -0,0 +1,587 @@
+use std::{fs, time::Duration};
+
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use serenity::async_trait;
+
+use crate::{
+    command::SlashCommand,
+    config::{KubernetesServer, KubernetesWorkload},
+    logs::{self, LogQuery},
+    server_commands::{
+        block_on, render_connect, render_endpoints, ServerCommands, ServerError, ServerStatus,
+    },
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// the annotation `kubectl rollout restart` sets, which makes the workload replace its pods
+const RESTARTED_AT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+
+impl KubernetesServer {
+    fn client(&self) -> Result<&reqwest::Client, String> {
+        self.client
+            .get_or_init(|| {
+                let mut builder = reqwest::Client::builder().timeout(REQUEST_TIMEOUT);
+
+                if let Some(ca_cert) = &self.ca_cert {
+                    let pem = fs::read(ca_cert).map_err(|err| {
+                        format!(
+                            "Failed to read ca certificate {}: {}",
+                            ca_cert.display(),
+                            err
+                        )
+                    })?;
+                    let cert =
+                        reqwest::Certificate::from_pem(&pem).map_err(|err| err.to_string())?;
+                    builder = builder.add_root_certificate(cert);
+                }
+
+                builder.build().map_err(|err| err.to_string())
+            })
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+
+    fn workload_path(&self) -> String {
+        let (kind, name) = match &self.workload {
+            KubernetesWorkload::Deployment(name) => ("deployments", name),
+            KubernetesWorkload::StatefulSet(name) => ("statefulsets", name),
+        };
+
+        format!(
+            "/apis/apps/v1/namespaces/{}/{}/{}",
+            self.namespace, kind, name
+        )
+    }
+
+    fn workload_name(&self) -> &str {
+        match &self.workload {
+            KubernetesWorkload::Deployment(name) | KubernetesWorkload::StatefulSet(name) => name,
+        }
+    }
+
+    /// Sends a request to the api server, returning the response body
+    async fn request(
+        &self,
+        command: SlashCommand,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        patch: Option<Value>,
+    ) -> Result<String, ServerError> {
+        let failed = |err: String| ServerError::CommandFailed(command, err);
+
+        let client = self.client().map_err(failed)?;
+        let token = fs::read_to_string(&self.token_file).map_err(|err| {
+            failed(format!(
+                "Failed to read token file {}: {}",
+                self.token_file.display(),
+                err
+            ))
+        })?;
+
+        let mut request = client
+            .request(
+                method,
+                format!("{}{}", self.api_url.trim_end_matches('/'), path),
+            )
+            .bearer_auth(token.trim())
+            .query(query);
+
+        if let Some(patch) = patch {
+            request = request
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    "application/merge-patch+json",
+                )
+                .body(patch.to_string());
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(failed(api_error(status, &body)))
+        }
+    }
+
+    async fn get_json(
+        &self,
+        command: SlashCommand,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<Value, ServerError> {
+        let body = self
+            .request(command, Method::GET, path, query, None)
+            .await?;
+
+        serde_json::from_str(&body)
+            .map_err(|err| ServerError::CommandFailed(command, err.to_string()))
+    }
+
+    async fn scale(&self, command: SlashCommand, replicas: u32) -> Result<(), ServerError> {
+        self.request(
+            command,
+            Method::PATCH,
+            &format!("{}/scale", self.workload_path()),
+            &[],
+            Some(json!({ "spec": { "replicas": replicas } })),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Gets the pods of the workload, found through its label selector
+    async fn pods(
+        &self,
+        command: SlashCommand,
+        workload: &Value,
+    ) -> Result<Vec<Value>, ServerError> {
+        let selector = workload["spec"]["selector"]["matchLabels"]
+            .as_object()
+            .map(|labels| {
+                labels
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value.as_str().unwrap_or_default()))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .filter(|selector| !selector.is_empty())
+            .ok_or_else(|| {
+                ServerError::CommandFailed(
+                    command,
+                    format!("Workload {} has no label selector", self.workload_name()),
+                )
+            })?;
+
+        let pods = self
+            .get_json(
+                command,
+                &format!("/api/v1/namespaces/{}/pods", self.namespace),
+                &[("labelSelector", selector)],
+            )
+            .await?;
+
+        Ok(pods["items"].as_array().cloned().unwrap_or_default())
+    }
+
+    async fn status(&self) -> Result<ServerStatus, ServerError> {
+        let workload = self
+            .get_json(SlashCommand::Status, &self.workload_path(), &[])
+            .await?;
+        let pods = self.pods(SlashCommand::Status, &workload).await?;
+        let replicas = workload["spec"]["replicas"].as_u64().unwrap_or(1);
+
+        Ok(match (replicas, pods.as_slice()) {
+            (0, []) => ServerStatus::Stopped,
+            (0, _) => ServerStatus::Unknown("Terminating".to_string()),
+            (_, pods) if pods.iter().any(is_ready) => ServerStatus::Running,
+            // a pod that can't start is shown as why, so it doesn't look like it's starting forever
+            (_, pods) => match pods.iter().find_map(failure_reason) {
+                Some(reason) => ServerStatus::Unknown(reason.to_string()),
+                // until a pod is scheduled, created and ready, the server is still starting
+                None => ServerStatus::Unknown("Starting".to_string()),
+            },
+        })
+    }
+
+    async fn logs(&self, query: &LogQuery) -> Result<String, ServerError> {
+        let workload = self
+            .get_json(SlashCommand::Logs, &self.workload_path(), &[])
+            .await?;
+        let pods = self.pods(SlashCommand::Logs, &workload).await?;
+
+        // a ready pod is the one serving players; otherwise any pod, e.g. one that is crashing
+        let pod = pods
+            .iter()
+            .find(|pod| is_ready(pod))
+            .or(pods.first())
+            .and_then(|pod| pod["metadata"]["name"].as_str())
+            .ok_or_else(|| {
+                ServerError::CommandFailed(
+                    SlashCommand::Logs,
+                    format!("Workload {} has no pods", self.workload_name()),
+                )
+            })?;
+
+        let mut params = vec![("tailLines", query.lines.to_string())];
+        if let Some(since) = query.since {
+            params.push(("sinceSeconds", since.as_secs().max(1).to_string()));
+        }
+
+        let logs = self
+            .request(
+                SlashCommand::Logs,
+                Method::GET,
+                &format!("/api/v1/namespaces/{}/pods/{}/log", self.namespace, pod),
+                &params,
+                None,
+            )
+            .await?;
+
+        Ok(logs::tail_lines(&logs, query.lines))
+    }
+}
+
+#[async_trait]
+impl ServerCommands for KubernetesServer {
+    async fn connect(
+        &self,
+        server_name: &str,
+        endpoint: Option<&str>,
+    ) -> Result<String, ServerError> {
+        render_endpoints(&self.connect, endpoint, |template| {
+            render_connect(template, server_name, self.query_address.as_deref(), |_| {
+                String::new()
+            })
+        })
+        .await
+    }
+
+    async fn join_link(&self, server_name: &str) -> Result<Option<String>, ServerError> {
+        match &self.join_link {
+            Some(link) => {
+                let rendered = render_connect(
+                    link.template(),
+                    server_name,
+                    self.query_address.as_deref(),
+                    |_| String::new(),
+                )
+                .await?;
+                Ok(Some(link.link(&rendered)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn start_server(&self) -> Result<(), ServerError> {
+        block_on(self.scale(SlashCommand::Start, 1))
+    }
+
+    fn stop_server(&self) -> Result<(), ServerError> {
+        block_on(self.scale(SlashCommand::Stop, 0))
+    }
+
+    fn restart_server(&self) -> Result<(), ServerError> {
+        let restarted_at = humantime::format_rfc3339_seconds(std::time::SystemTime::now());
+        let patch = json!({
+            "spec": {
+                "template": {
+                    "metadata": {
+                        "annotations": { RESTARTED_AT_ANNOTATION: restarted_at.to_string() }
+                    }
+                }
+            }
+        });
+
+        block_on(self.request(
+            SlashCommand::Restart,
+            Method::PATCH,
+            &self.workload_path(),
+            &[],
+            Some(patch),
+        ))
+        .map(|_| ())
+    }
+
+    fn get_status(&self) -> Result<ServerStatus, ServerError> {
+        block_on(self.status())
+    }
+
+    fn get_logs(&self, query: &LogQuery) -> Result<String, ServerError> {
+        block_on(self.logs(query))
+    }
+}
+
+/// Returns true if the pod is running, passing its readiness checks, and not being deleted
+fn is_ready(pod: &Value) -> bool {
+    let ready = pod["status"]["conditions"]
+        .as_array()
+        .is_some_and(|conditions| {
+            conditions
+                .iter()
+                .any(|condition| condition["type"] == "Ready" && condition["status"] == "True")
+        });
+
+    pod["status"]["phase"] == "Running" && ready && pod["metadata"]["deletionTimestamp"].is_null()
+}
+
+/// Why the pod can't run the server, e.g. CrashLoopBackOff or ImagePullBackOff, if it's failing
+fn failure_reason(pod: &Value) -> Option<&str> {
+    if pod["status"]["phase"] == "Failed" {
+        return Some("Failed");
+    }
+
+    pod["status"]["containerStatuses"]
+        .as_array()?
+        .iter()
+        .filter_map(|container| container["state"]["waiting"]["reason"].as_str())
+        // waiting to be created or initialized is part of starting normally
+        .find(|reason| !matches!(*reason, "ContainerCreating" | "PodInitializing"))
+}
+
+// the api server describes errors with a Status object
+fn api_error(status: StatusCode, body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|body| body["message"].as_str().map(str::to_string))
+        .map_or_else(
+            || format!("Api server responded with {}", status),
+            |message| format!("Api server responded with {}: {}", status, message),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use axum::http::{header, Method, StatusCode};
+    use tempfile::TempPath;
+
+    use super::*;
+    use crate::{
+        config::ServerType,
+        mock_http::{secret_file, MockServer},
+    };
+
+    const WORKLOAD_PATH: &str = "/apis/apps/v1/namespaces/games/deployments/valheim";
+    const PODS_PATH: &str = "/api/v1/namespaces/games/pods";
+
+    fn serve(replicas: u64, pods: Vec<Value>) -> MockServer {
+        let workload = json!({
+            "spec": {
+                "replicas": replicas,
+                "selector": { "matchLabels": { "app": "valheim" } },
+            }
+        });
+        let pods = json!({ "items": pods });
+
+        MockServer::serve(move |request| {
+            let response = match (&request.method, request.uri.path()) {
+                (&Method::GET, WORKLOAD_PATH) => workload.clone(),
+                (&Method::GET, PODS_PATH) => pods.clone(),
+                (&Method::PATCH, _) => json!({}),
+                _ => {
+                    let status = json!({
+                        "kind": "Status",
+                        "message": "deployments.apps \"valheim\" not found",
+                    });
+
+                    return (StatusCode::NOT_FOUND, status.to_string());
+                }
+            };
+
+            (StatusCode::OK, response.to_string())
+        })
+    }
+
+    // the token file is removed once its path is dropped
+    fn server(api: &MockServer) -> (KubernetesServer, TempPath) {
+        let token_file = secret_file("test-token\n");
+        let server = KubernetesServer {
+            api_url: api.url("/"),
+            token_file: token_file.to_path_buf(),
+            ca_cert: None,
+            namespace: "games".to_string(),
+            workload: KubernetesWorkload::Deployment("valheim".to_string()),
+            connect: ron::from_str("\"{public_ip}:2456\"").unwrap(),
+            guild_ids: Default::default(),
+            notifications: None,
+            log_redactions: vec![],
+            memory_weight: 0,
+            query_address: None,
+            join_link: None,
+            client: OnceLock::new(),
+        };
+
+        (server, token_file)
+    }
+
+    fn pod(phase: &str, ready: bool) -> Value {
+        json!({
+            "metadata": { "name": "valheim-abc" },
+            "status": {
+                "phase": phase,
+                "conditions": [{ "type": "Ready", "status": if ready { "True" } else { "False" } }],
+            }
+        })
+    }
+
+    fn waiting_pod(reason: &str) -> Value {
+        json!({
+            "metadata": { "name": "valheim-abc" },
+            "status": {
+                "phase": "Running",
+                "conditions": [{ "type": "Ready", "status": "False" }],
+                "containerStatuses": [{ "state": { "waiting": { "reason": reason } } }],
+            }
+        })
+    }
+
+    async fn status(replicas: u64, pods: Vec<Value>) -> ServerStatus {
+        let (server, _token_file) = server(&serve(replicas, pods));
+
+        server.status().await.unwrap()
+    }
+
+    fn unknown(detail: &str) -> ServerStatus {
+        ServerStatus::Unknown(detail.to_string())
+    }
+
+    #[tokio::test]
+    async fn maps_replicas_and_pods_to_status() {
+        assert_eq!(status(0, vec![]).await, ServerStatus::Stopped);
+        assert_eq!(
+            status(0, vec![pod("Running", true)]).await,
+            unknown("Terminating")
+        );
+        assert_eq!(
+            status(1, vec![pod("Running", true)]).await,
+            ServerStatus::Running
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_every_starting_stage_the_same() {
+        assert_eq!(status(1, vec![]).await, unknown("Starting"));
+        assert_eq!(
+            status(1, vec![pod("Pending", false)]).await,
+            unknown("Starting")
+        );
+        assert_eq!(
+            status(1, vec![pod("Running", false)]).await,
+            unknown("Starting")
+        );
+        assert_eq!(
+            status(1, vec![waiting_pod("ContainerCreating")]).await,
+            unknown("Starting")
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_failing_pods() {
+        assert_eq!(
+            status(1, vec![waiting_pod("CrashLoopBackOff")]).await,
+            unknown("CrashLoopBackOff")
+        );
+        assert_eq!(
+            status(1, vec![pod("Failed", false)]).await,
+            unknown("Failed")
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_pods_by_label_selector() {
+        let api = serve(1, vec![pod("Running", true)]);
+        let (server, _token_file) = server(&api);
+
+        server.status().await.unwrap();
+
+        let received = api.received();
+        let pods = received
+            .iter()
+            .find(|request| request.uri.path() == PODS_PATH)
+            .unwrap();
+        assert_eq!(pods.uri.query(), Some("labelSelector=app%3Dvalheim"));
+        assert_eq!(pods.headers[header::AUTHORIZATION], "Bearer test-token");
+    }
+
+    fn patches(api: &MockServer) -> Vec<(String, Value)> {
+        api.received()
+            .iter()
+            .filter(|request| request.method == Method::PATCH)
+            .map(|request| {
+                assert_eq!(
+                    request.headers[header::CONTENT_TYPE],
+                    "application/merge-patch+json"
+                );
+                (
+                    request.uri.path().to_string(),
+                    serde_json::from_slice(&request.body).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scales_to_start_and_stop() {
+        let api = serve(0, vec![]);
+        let (server, _token_file) = server(&api);
+
+        server.start_server().unwrap();
+        server.stop_server().unwrap();
+
+        let scale_path = format!("{}/scale", WORKLOAD_PATH);
+        assert_eq!(
+            patches(&api),
+            vec![
+                (scale_path.clone(), json!({ "spec": { "replicas": 1 } })),
+                (scale_path, json!({ "spec": { "replicas": 0 } })),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stops_crash_looping_server() {
+        let api = serve(1, vec![waiting_pod("CrashLoopBackOff")]);
+        let (server, _token_file) = server(&api);
+        let server = ServerType::Kubernetes(server);
+
+        server.stop_server().unwrap();
+
+        assert_eq!(
+            patches(&api),
+            vec![(
+                format!("{}/scale", WORKLOAD_PATH),
+                json!({ "spec": { "replicas": 0 } })
+            )]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn restarts_with_rollout_annotation() {
+        let api = serve(1, vec![pod("Running", true)]);
+        let (server, _token_file) = server(&api);
+
+        server.restart_server().unwrap();
+
+        let patches = patches(&api);
+        assert_eq!(patches.len(), 1);
+
+        let (path, patch) = &patches[0];
+        assert_eq!(path, WORKLOAD_PATH);
+
+        let restarted_at = patch["spec"]["template"]["metadata"]["annotations"]
+            [RESTARTED_AT_ANNOTATION]
+            .as_str()
+            .unwrap();
+        assert!(humantime::parse_rfc3339(restarted_at).is_ok());
+    }
+
+    #[tokio::test]
+    async fn reports_api_errors() {
+        let (mut server, _token_file) = server(&serve(1, vec![]));
+        server.workload = KubernetesWorkload::StatefulSet("valheim".to_string());
+
+        let err = server.status().await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ServerError::CommandFailed(
+                SlashCommand::Status,
+                "Api server responded with 404 Not Found: deployments.apps \"valheim\" not found"
+                    .to_string()
+            )
+            .to_string()
+        );
+    }
+}
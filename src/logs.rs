@@ -0,0 +1,314 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    process::Stdio,
+    sync::Arc,
+    time::Duration,
+};
+
+use regex::Regex;
+use serenity::{
+    http::Http,
+    model::{
+        channel::Channel,
+        id::{ChannelId, MessageId},
+    },
+    prelude::{RwLock, TypeMap},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+    sync::mpsc,
+    time::Instant,
+};
+
+use tracing::{error, info, instrument};
+
+use crate::{notifications::ServerStatusKey, server_commands::ServerStatus};
+
+pub const DEFAULT_LOG_LINES: usize = 100;
+pub const MAX_LOG_LINES: usize = 5000;
+
+// only the end of large log files is read when tailing them
+const MAX_LOG_FILE_BYTES: u64 = 4 * 1024 * 1024;
+
+const REDACTED: &str = "[REDACTED]";
+
+pub const DEFAULT_FOLLOW_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+pub const MAX_FOLLOW_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+// new lines are batched into a message at most this often, to stay under discord's rate limits
+const FOLLOW_BATCH_INTERVAL: Duration = Duration::from_secs(3);
+// lines beyond what fits in this many messages per batch are dropped
+const FOLLOW_MAX_MESSAGES_PER_BATCH: usize = 2;
+// how often to check whether the thread was archived or the server stopped
+const FOLLOW_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// leaves room for the code block around the lines
+const FOLLOW_MAX_MESSAGE_LENGTH: usize = 1900;
+
+/// The portion of the server logs to fetch
+#[derive(Debug, Clone)]
+pub struct LogQuery {
+    /// Number of lines to fetch, counting back from the most recent
+    pub lines: usize,
+    /// Only fetch lines logged within this duration, if supported by the server
+    pub since: Option<Duration>,
+}
+
+impl Default for LogQuery {
+    fn default() -> Self {
+        Self {
+            lines: DEFAULT_LOG_LINES,
+            since: None,
+        }
+    }
+}
+
+/// Compiles the configured redaction patterns
+pub fn compile_redactions(patterns: &[String]) -> Result<Vec<Regex>, regex::Error> {
+    patterns.iter().map(|pattern| Regex::new(pattern)).collect()
+}
+
+/// Replaces every match of the given redactions with a placeholder
+pub fn redact(logs: &str, redactions: &[Regex]) -> String {
+    let mut logs = logs.to_string();
+
+    for regex in redactions {
+        logs = regex.replace_all(&logs, REDACTED).into_owned();
+    }
+
+    logs
+}
+
+/// Returns the last `count` lines of the text
+pub fn tail_lines(text: &str, count: usize) -> String {
+    let lines = text.lines().collect::<Vec<_>>();
+    let start = lines.len().saturating_sub(count);
+
+    lines[start..].join("\n")
+}
+
+/// Returns the last `count` lines of the file at `path`
+pub fn tail_file(path: &Path, count: usize) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    file.seek(SeekFrom::Start(len.saturating_sub(MAX_LOG_FILE_BYTES)))?;
+
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    Ok(tail_lines(&String::from_utf8_lossy(&buf), count))
+}
+
+/// A live stream of new log lines from a server
+pub struct LogStream {
+    // kept so the process is killed when the stream is dropped
+    _child: Child,
+    lines: mpsc::UnboundedReceiver<String>,
+}
+
+impl LogStream {
+    /// Spawns a command that prints new log lines until the server stops
+    pub fn spawn(cmd: &str, args: &[String]) -> io::Result<Self> {
+        let mut child = Command::new(cmd)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(forward_lines(stdout, tx.clone()));
+        }
+
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(forward_lines(stderr, tx));
+        }
+
+        Ok(Self {
+            _child: child,
+            lines: rx,
+        })
+    }
+
+    /// Waits for the next line; returns `None` once the command exits
+    pub async fn next_line(&mut self) -> Option<String> {
+        self.lines.recv().await
+    }
+}
+
+async fn forward_lines<R: AsyncRead + Unpin>(reader: R, tx: mpsc::UnboundedSender<String>) {
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if tx.send(line).is_err() {
+            break;
+        }
+    }
+}
+
+/// A request to stream server logs into a thread
+pub struct LogFollow {
+    pub server_name: String,
+    pub stream: LogStream,
+    /// Only lines matching the filter are forwarded
+    pub filter: Option<Regex>,
+    pub redactions: Vec<Regex>,
+    pub timeout: Duration,
+}
+
+/// Creates a thread on the given message and streams the logs into it
+/// until the timeout elapses, the server stops, or the thread is archived.
+pub async fn follow_in_thread(
+    data: Arc<RwLock<TypeMap>>,
+    http: Arc<Http>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    follow: LogFollow,
+) {
+    let thread = channel_id
+        .create_public_thread(&http, message_id, |thread| {
+            thread
+                .name(format!("{} logs", follow.server_name))
+                .auto_archive_duration(60)
+        })
+        .await;
+
+    match thread {
+        Ok(thread) => {
+            tokio::spawn(stream_to_thread(data, http, thread.id, follow));
+        }
+        Err(err) => error!(%err, "Cannot create log thread"),
+    }
+}
+
+#[instrument(skip_all, fields(server = %follow.server_name, thread = thread_id.0))]
+async fn stream_to_thread(
+    data: Arc<RwLock<TypeMap>>,
+    http: Arc<Http>,
+    thread_id: ChannelId,
+    mut follow: LogFollow,
+) {
+    info!("Following logs");
+
+    let deadline = Instant::now() + follow.timeout;
+    let mut batch = Vec::new();
+    let mut batch_interval = tokio::time::interval(FOLLOW_BATCH_INTERVAL);
+    let mut check_interval = tokio::time::interval(FOLLOW_CHECK_INTERVAL);
+
+    let reason = loop {
+        tokio::select! {
+            line = follow.stream.next_line() => match line {
+                Some(line) => {
+                    if follow.filter.as_ref().is_none_or(|filter| filter.is_match(&line)) {
+                        batch.push(redact(&line, &follow.redactions));
+                    }
+                }
+                None => break "the log stream ended",
+            },
+            _ = batch_interval.tick() => {
+                if let Err(err) = send_batch(&http, thread_id, &mut batch).await {
+                    error!(%err, "Cannot send logs to thread");
+                    break "logs could not be sent";
+                }
+            }
+            _ = check_interval.tick() => {
+                if is_archived(&http, thread_id).await {
+                    info!("Log thread was archived");
+                    return;
+                }
+
+                let stopped = {
+                    let data = data.read().await;
+                    let statuses = data.get::<ServerStatusKey>().unwrap();
+
+                    matches!(statuses.get(&follow.server_name), Some(ServerStatus::Stopped))
+                };
+
+                if stopped {
+                    break "the server stopped";
+                }
+            }
+            _ = tokio::time::sleep_until(deadline) => break "the timeout elapsed",
+        }
+    };
+
+    let _ = send_batch(&http, thread_id, &mut batch).await;
+    let _ = thread_id
+        .say(&http, format!("Stopped following logs; {}.", reason))
+        .await;
+
+    info!(reason, "Stopped following logs");
+}
+
+async fn send_batch(
+    http: &Http,
+    thread_id: ChannelId,
+    batch: &mut Vec<String>,
+) -> serenity::Result<()> {
+    let mut messages: Vec<String> = vec![];
+    let mut message = String::new();
+
+    for line in batch.drain(..) {
+        // lines are truncated so any single line fits in a message
+        let line = line
+            .chars()
+            .take(FOLLOW_MAX_MESSAGE_LENGTH - 1)
+            .collect::<String>()
+            .replace("```", "'''");
+
+        // the limit is in characters, not bytes
+        if !message.is_empty()
+            && message.chars().count() + line.chars().count() + 1 > FOLLOW_MAX_MESSAGE_LENGTH
+        {
+            messages.push(std::mem::take(&mut message));
+        }
+
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        message.push_str(&line);
+    }
+
+    if !message.is_empty() {
+        messages.push(message);
+    }
+
+    let dropped = messages.len().saturating_sub(FOLLOW_MAX_MESSAGES_PER_BATCH);
+
+    for message in messages.iter().take(FOLLOW_MAX_MESSAGES_PER_BATCH) {
+        thread_id
+            .say(http, format!("```\n{}\n```", message))
+            .await?;
+    }
+
+    if dropped > 0 {
+        thread_id
+            .say(
+                http,
+                format!(
+                    "*Skipped {} messages worth of logs to avoid rate limits*",
+                    dropped
+                ),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn is_archived(http: &Http, thread_id: ChannelId) -> bool {
+    match http.get_channel(thread_id.0).await {
+        Ok(Channel::Guild(channel)) => channel
+            .thread_metadata
+            .is_some_and(|metadata| metadata.archived),
+        Ok(_) => false,
+        // the thread was likely deleted
+        Err(_) => true,
+    }
+}
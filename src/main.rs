@@ -1,36 +1,78 @@
 use std::{
     collections::{hash_map::RandomState, HashMap, HashSet},
     str::FromStr,
-    time::Duration,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
-use crate::command::SlashCommand;
-use config::{ServerMap, VoiceChannelConfigs};
+use crate::command::{CommandReply, SlashCommand};
+use admission::{AdmissionKey, ServerActivityKey};
+use audit::{AuditActor, AuditChannelKey, AuditEntry};
+use backup::{BackupQueueKey, BackupsInProgressKey};
+use config::{ServerMap, VoiceChannelConfigs, VoiceChatConfig};
+use dashmap::{DashMap, DashSet};
+use join::{JoinRedirects, JoinRedirectsKey};
+use notifications::{ExpectedChangesKey, ServerStatusKey};
+use power::HostsKey;
 use rand::Rng;
+use store::{PendingVoiceMessage, Store, StoreKey};
+use update::UpdatesInProgressKey;
+use webhooks::{WebhookEvent, Webhooks, WebhooksKey};
 
 use serenity::{
     async_trait,
+    http::Http,
     model::{
+        application::component::ButtonStyle,
+        channel::AttachmentType,
         id::{ChannelId, RoleId, UserId},
         prelude::{
-            interaction::{Interaction, InteractionResponseType},
-            GuildId, Ready,
+            interaction::{
+                application_command::ApplicationCommandInteraction,
+                message_component::MessageComponentInteraction, Interaction,
+                InteractionResponseType,
+            },
+            Guild, GuildId, Ready, ResumedEvent,
         },
         voice::VoiceState,
     },
     prelude::*,
 };
 use strum::IntoEnumIterator;
+use tracing::{debug, error, info, instrument, trace, warn};
 
+mod admission;
+mod audit;
+mod backup;
 mod command;
 mod config;
+mod dashboard;
+mod join;
+mod kubernetes;
+mod logs;
+mod metrics;
+#[cfg(test)]
+mod mock_http;
+mod notifications;
+mod power;
+mod pterodactyl;
+mod public_ip;
+mod query;
 mod server_commands;
+mod stats;
+mod store;
+mod telemetry;
+mod template;
+mod update;
+mod web;
+mod webhooks;
 
 const DELAY: Duration = Duration::from_secs(15);
 
 struct ServerKey;
 impl TypeMapKey for ServerKey {
-    type Value = ServerMap;
+    // shared so long-running server commands don't need to hold the data lock
+    type Value = Arc<ServerMap>;
 }
 
 struct VoiceChatConfigKey;
@@ -48,7 +90,9 @@ struct Handler;
 #[async_trait]
 impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, ready: Ready) {
-        println!("{} is connected!", ready.user.name);
+        info!(user = %ready.user.name, "Connected to discord");
+        metrics::record_gateway_connect();
+
         let data = ctx.data.read().await;
         let servers = data.get::<ServerKey>().unwrap();
 
@@ -62,10 +106,10 @@ impl EventHandler for Handler {
                 .map(|s| s.0)
                 .collect::<Vec<_>>();
 
-            let commands = GuildId::set_application_commands(&guild_id, &ctx.http, |commands| {
+            let commands = GuildId::set_application_commands(guild_id, &ctx.http, |commands| {
                 for slash_command in SlashCommand::iter() {
                     commands.create_application_command(|command| {
-                        println!("Registering command {:#?}", slash_command);
+                        debug!(command = %slash_command, "Registering command");
                         slash_command.register(&servers, command);
 
                         command
@@ -78,133 +122,352 @@ impl EventHandler for Handler {
 
             match commands {
                 Ok(commands) => {
-                    println!(
-                        "Guild {} registered slash commands: {:#?}",
-                        guild_id, commands
+                    info!(
+                        guild = guild_id.0,
+                        commands = commands.len(),
+                        "Registered slash commands"
                     );
                 }
                 Err(err) => {
-                    println!("Cannot register slash commands: {}", err);
+                    error!(guild = guild_id.0, %err, "Cannot register slash commands");
                 }
             }
         }
     }
 
+    async fn resume(&self, _ctx: Context, _resumed: ResumedEvent) {
+        info!("Resumed discord session");
+        metrics::record_gateway_reconnect();
+    }
+
+    async fn guild_create(&self, ctx: Context, guild: Guild, _is_new: bool) {
+        rebuild_vc_state(&ctx, &guild).await;
+    }
+
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::MessageComponent(component) = &interaction {
+            handle_component(&ctx, component).await;
+            return;
+        }
+
         if let Interaction::ApplicationCommand(command_interaction) = interaction {
-            println!("Received command interaction: {:#?}", command_interaction);
-            let command = SlashCommand::from_str(&command_interaction.data.name);
-
-            match command {
-                Ok(command) => {
-                    let data = ctx.data.read().await;
-                    let servers = data.get::<ServerKey>().unwrap();
-
-                    if let Err(why) = command_interaction
-                        .create_interaction_response(&ctx.http, |response| {
-                            response
-                                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
-                                .interaction_response_data(|message| {
-                                    message.content(command.pending_msg())
+            handle_command(&ctx, command_interaction).await;
+        }
+    }
+
+    async fn voice_state_update(&self, ctx: Context, old: Option<VoiceState>, new: VoiceState) {
+        handle_voice_state_update(&ctx, old, new).await;
+    }
+}
+
+#[instrument(skip_all, fields(
+    guild = ?command_interaction.guild_id.map(|id| id.0),
+    user = command_interaction.user.id.0,
+    command = %command_interaction.data.name,
+    server = ?SlashCommand::server_name(&command_interaction.data.options),
+))]
+async fn handle_command(ctx: &Context, command_interaction: ApplicationCommandInteraction) {
+    info!("Received command");
+    let command = SlashCommand::from_str(&command_interaction.data.name);
+
+    match command {
+        Ok(command) => {
+            if let Err(why) = command_interaction
+                .create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                        .interaction_response_data(|message| message.content(command.pending_msg()))
+                })
+                .await
+            {
+                error!(%why, "Cannot respond to slash command");
+            } else {
+                let guild_id = command_interaction.guild_id.unwrap_or_default();
+                let started = Instant::now();
+                let reply = command
+                    .run(&ctx.data, guild_id, &command_interaction.data.options)
+                    .await;
+
+                metrics::record_command(command, reply.failed);
+
+                audit::record(
+                    &ctx.data,
+                    &ctx.http,
+                    AuditEntry {
+                        time: SystemTime::now(),
+                        guild_id,
+                        actor: AuditActor::User(command_interaction.user.id),
+                        server_name: SlashCommand::server_name(&command_interaction.data.options)
+                            .map(str::to_string),
+                        command: command.to_string(),
+                        result: reply.content.clone(),
+                        duration: started.elapsed(),
+                    },
+                )
+                .await;
+
+                let CommandReply {
+                    content,
+                    attachments,
+                    follow,
+                    buttons,
+                    links,
+                    ..
+                } = reply;
+
+                match command_interaction
+                    .create_followup_message(&ctx.http, |response| {
+                        for (filename, data) in attachments {
+                            response.add_file(AttachmentType::Bytes {
+                                data: data.into(),
+                                filename,
+                            });
+                        }
+
+                        if !buttons.is_empty() || !links.is_empty() {
+                            response.components(|components| {
+                                components.create_action_row(|row| {
+                                    for button in buttons {
+                                        row.create_button(|b| {
+                                            b.style(ButtonStyle::Danger)
+                                                .label(button.label)
+                                                .custom_id(button.custom_id)
+                                        });
+                                    }
+
+                                    for link in links {
+                                        row.create_button(|b| {
+                                            b.style(ButtonStyle::Link)
+                                                .label(link.label)
+                                                .url(link.url)
+                                        });
+                                    }
+
+                                    row
                                 })
-                        })
-                        .await
-                    {
-                        println!("Cannot respond to slash command: {}", why);
-                    } else {
-                        let content = command
-                            .run(
-                                servers,
-                                command_interaction.guild_id.unwrap_or_default(),
-                                &command_interaction.data.options,
+                            });
+                        }
+
+                        response.content(content)
+                    })
+                    .await
+                {
+                    Ok(message) => {
+                        if let Some(follow) = follow {
+                            logs::follow_in_thread(
+                                ctx.data.clone(),
+                                ctx.http.clone(),
+                                message.channel_id,
+                                message.id,
+                                follow,
                             )
                             .await;
-
-                        if let Err(why) = command_interaction
-                            .create_followup_message(&ctx.http, |response| {
-                                response.content(content)
-                            })
-                            .await
-                        {
-                            println!("Cannot follow-up to slash command: {}", why);
                         }
                     }
+                    Err(why) => error!(%why, "Cannot follow-up to slash command"),
+                }
+
+                if command.changes_status() {
+                    if let Some(server_name) =
+                        SlashCommand::server_name(&command_interaction.data.options)
+                    {
+                        notifications::check_server_status(
+                            &ctx.data,
+                            &ctx.http,
+                            server_name,
+                            Some(AuditActor::User(command_interaction.user.id)),
+                        )
+                        .await;
+                    }
                 }
-                Err(err) => println!("Cannot parse slash command : {}", err),
             }
         }
+        Err(err) => warn!(%err, "Cannot parse slash command"),
     }
+}
 
-    async fn voice_state_update(&self, ctx: Context, old: Option<VoiceState>, new: VoiceState) {
-        // println!("Got voice state update: {:?} to {:?}", old, new);
-
-        let user = old
-            .as_ref()
-            .unwrap_or(&new)
-            .member
-            .as_ref()
-            .map(|v| &v.user);
-
-        match user {
-            Some(user) => {
-                if user.bot {
-                    println!("skipping bot user");
-                    return;
-                }
+#[instrument(skip_all, fields(guild = ?new.guild_id.map(|id| id.0), user = new.user_id.0))]
+async fn handle_voice_state_update(ctx: &Context, old: Option<VoiceState>, new: VoiceState) {
+    trace!(?old, ?new, "Got voice state update");
+
+    let user = old
+        .as_ref()
+        .unwrap_or(&new)
+        .member
+        .as_ref()
+        .map(|v| &v.user);
+
+    match user {
+        Some(user) => {
+            if user.bot {
+                debug!("skipping bot user");
+                return;
+            }
 
-                let user_id = user.id;
-
-                let old_channel = old.and_then(|v| v.channel_id);
-                let new_channel = new.channel_id;
-
-                match (old_channel, new_channel) {
-                    (None, Some(new)) => handle_vc(&ctx, new, user_id, true).await,
-                    (Some(old), None) => handle_vc(&ctx, old, user_id, false).await,
-                    (Some(old_channel), Some(new_channel)) => {
-                        let voice_configs = {
-                            let data = ctx.data.read().await;
-                            let value = data.get::<VoiceChatConfigKey>();
-
-                            value.cloned().unwrap()
-                        };
-
-                        let old_roles: HashSet<_> = voice_configs
-                            .iter()
-                            .filter_map(|v| {
-                                if v.1.voice_channel_ids.contains(&old_channel) {
-                                    Some(*v.0)
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect();
-
-                        let new_roles: HashSet<_> = voice_configs
-                            .iter()
-                            .filter_map(|v| {
-                                if v.1.voice_channel_ids.contains(&new_channel) {
-                                    Some(*v.0)
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect();
-
-                        if new_roles.len() != old_roles.len()
-                            || new_roles.intersection(&old_roles).count() != new_roles.len()
-                        {
-                            println!("swapping user channels");
-                            handle_vc(&ctx, new_channel, user_id, true).await;
-                            handle_vc(&ctx, old_channel, user_id, false).await;
-                        } else {
-                            println!("skipping VC update; roles match");
-                        }
+            let user_id = user.id;
+
+            let old_channel = old.and_then(|v| v.channel_id);
+            let new_channel = new.channel_id;
+
+            match (old_channel, new_channel) {
+                (None, Some(new)) => handle_vc(ctx, new, user_id, true).await,
+                (Some(old), None) => handle_vc(ctx, old, user_id, false).await,
+                (Some(old_channel), Some(new_channel)) => {
+                    let voice_configs = {
+                        let data = ctx.data.read().await;
+                        let value = data.get::<VoiceChatConfigKey>();
+
+                        value.cloned().unwrap()
+                    };
+
+                    let old_roles: HashSet<_> = voice_configs
+                        .iter()
+                        .filter_map(|v| {
+                            if v.1.voice_channel_ids.contains(&old_channel) {
+                                Some(*v.0)
+                            } else {
+                                None
+                            }
+                        })
+                        .collect();
+
+                    let new_roles: HashSet<_> = voice_configs
+                        .iter()
+                        .filter_map(|v| {
+                            if v.1.voice_channel_ids.contains(&new_channel) {
+                                Some(*v.0)
+                            } else {
+                                None
+                            }
+                        })
+                        .collect();
+
+                    if new_roles.len() != old_roles.len()
+                        || new_roles.intersection(&old_roles).count() != new_roles.len()
+                    {
+                        debug!("swapping user channels");
+                        handle_vc(ctx, new_channel, user_id, true).await;
+                        handle_vc(ctx, old_channel, user_id, false).await;
+                    } else {
+                        debug!("skipping VC update; roles match");
                     }
-                    (None, None) => println!("skipping VC update"),
                 }
+                (None, None) => debug!("skipping VC update"),
             }
-            None => return,
         }
+        None => return,
+    }
+}
+
+#[instrument(skip_all, fields(
+    guild = ?component.guild_id.map(|id| id.0),
+    user = component.user.id.0,
+    custom_id = %component.data.custom_id,
+))]
+async fn handle_component(ctx: &Context, component: &MessageComponentInteraction) {
+    let (stop_name, start_name) = match admission::parse_swap_id(&component.data.custom_id) {
+        Some(ids) => ids,
+        None => {
+            warn!("Unknown component interaction");
+            return;
+        }
+    };
+
+    if let Err(why) = component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(format!(
+                        "Stopping server {} to start server {}...",
+                        stop_name, start_name
+                    ))
+                })
+        })
+        .await
+    {
+        error!(%why, "Cannot respond to component interaction");
+        return;
+    }
+
+    let guild_id = component.guild_id.unwrap_or_default();
+    let started = Instant::now();
+    let content = admission::run_swap(&ctx.data, guild_id, &stop_name, &start_name).await;
+
+    audit::record(
+        &ctx.data,
+        &ctx.http,
+        AuditEntry {
+            time: SystemTime::now(),
+            guild_id,
+            actor: AuditActor::User(component.user.id),
+            server_name: Some(start_name.clone()),
+            command: format!("swap (stopping {})", stop_name),
+            result: content.clone(),
+            duration: started.elapsed(),
+        },
+    )
+    .await;
+
+    if let Err(why) = component
+        .create_followup_message(&ctx.http, |response| response.content(content))
+        .await
+    {
+        error!(%why, "Cannot follow-up to component interaction");
+    }
+
+    for server_name in [&stop_name, &start_name] {
+        notifications::check_server_status(
+            &ctx.data,
+            &ctx.http,
+            server_name,
+            Some(AuditActor::User(component.user.id)),
+        )
+        .await;
+    }
+}
+
+/// Replaces the tracked occupancy of the guild's voice channels with who is in them now, so
+/// sessions already in progress (or that ended) while the bot was offline aren't announced
+async fn rebuild_vc_state(ctx: &Context, guild: &Guild) {
+    let mut data = ctx.data.write().await;
+    let voice_configs = data.get::<VoiceChatConfigKey>().cloned().unwrap();
+    let store = data.get::<StoreKey>().unwrap().clone();
+    let channels = data.get_mut::<VoiceChatStateKey>().unwrap();
+
+    let configs = voice_configs
+        .iter()
+        .filter(|(_, config)| config.guild_ids.contains(&guild.id));
+
+    for (id, config) in configs {
+        let users: HashSet<UserId> = guild
+            .voice_states
+            .values()
+            .filter(|state| {
+                state
+                    .channel_id
+                    .is_some_and(|channel_id| config.voice_channel_ids.contains(&channel_id))
+            })
+            .filter(|state| {
+                let member = state.member.as_ref().or(guild.members.get(&state.user_id));
+                !member.is_some_and(|member| member.user.bot)
+            })
+            .map(|state| state.user_id)
+            .collect();
+
+        info!(
+            role = id.0,
+            guild = guild.id.0,
+            users = ?users,
+            "Rebuilt voice state"
+        );
+
+        if let Err(err) = store.replace_voice_members(*id, &users) {
+            error!(%err, "Failed to store voice state");
+        }
+
+        metrics::set_voice_members(*id, users.len());
+        channels.insert(*id, users);
     }
 }
 
@@ -221,8 +484,9 @@ async fn handle_vc(ctx: &Context, channel_id: ChannelId, user_id: UserId, is_add
         .filter(|(_, v)| v.voice_channel_ids.contains(&channel_id));
 
     for (id, config) in configs {
-        let (old_len, new_len) = {
+        let (old_len, new_len, store) = {
             let mut data = ctx.data.write().await;
+            let store = data.get::<StoreKey>().unwrap().clone();
             let channels = data.get_mut::<VoiceChatStateKey>().unwrap();
 
             let entry = channels.entry(id).or_default();
@@ -234,11 +498,16 @@ async fn handle_vc(ctx: &Context, channel_id: ChannelId, user_id: UserId, is_add
                 entry.remove(&user_id);
             }
 
-            println!("Got entry: {entry:?}");
+            debug!(role = id.0, ?entry, "Updated voice state");
             let new_len = entry.len();
-            (old_len, new_len)
+            metrics::set_voice_members(id, new_len);
+            (old_len, new_len, store)
         };
 
+        if let Err(err) = store.set_voice_member(id, user_id, is_add) {
+            error!(%err, "Failed to store voice state");
+        }
+
         let condition = if is_add {
             old_len == 0 && new_len > old_len && !config.start_msgs.is_empty()
         } else {
@@ -246,79 +515,135 @@ async fn handle_vc(ctx: &Context, channel_id: ChannelId, user_id: UserId, is_add
         };
 
         if condition {
-            let ctx_data = ctx.data.clone();
+            // stored so the message is still sent if the bot restarts while waiting
+            let pending = PendingVoiceMessage {
+                role_id: id,
+                is_start: is_add,
+                due_at: SystemTime::now() + DELAY,
+                user_id: Some(user_id),
+                channel_id: Some(channel_id),
+            };
+
+            if let Err(err) = store.add_pending_voice_message(&pending) {
+                error!(%err, "Failed to store pending voice message");
+            }
 
-            #[cfg(not(debug_assertions))]
-            let http = ctx.http.clone();
+            debug!(role = id.0, is_start = is_add, "starting msg task");
 
-            println!("starting msg task");
+            let _ = tokio::spawn(send_vc_msg(
+                ctx.data.clone(),
+                ctx.http.clone(),
+                config,
+                pending,
+            ))
+            .await;
+        }
+    }
+}
 
-            let _ = tokio::spawn(async move {
-                tokio::time::sleep(DELAY).await;
-                let mut data = ctx_data.write().await;
-                let channels = data.get_mut::<VoiceChatStateKey>().unwrap();
+/// Waits until the message is due, then sends it if the voice channel is still occupied
+/// (for start messages) or still empty (for end messages)
+#[instrument(skip_all, fields(role = pending.role_id.0, is_start = pending.is_start))]
+async fn send_vc_msg(
+    ctx_data: Arc<RwLock<TypeMap>>,
+    http: Arc<Http>,
+    config: VoiceChatConfig,
+    pending: PendingVoiceMessage,
+) {
+    let id = pending.role_id;
+    let is_add = pending.is_start;
+
+    if let Ok(delay) = pending.due_at.duration_since(SystemTime::now()) {
+        tokio::time::sleep(delay).await;
+    }
 
-                let entry = channels.entry(id).or_default();
+    let mut data = ctx_data.write().await;
 
-                println!("Got entry after wait: {entry:?}");
+    let store = data.get::<StoreKey>().unwrap().clone();
+    if let Err(err) = store.remove_pending_voice_message(id, is_add) {
+        error!(%err, "Failed to remove pending voice message");
+    }
 
-                let skip_condition = if is_add {
-                    entry.len() <= 0
-                } else {
-                    entry.len() > 0
-                };
+    let channels = data.get_mut::<VoiceChatStateKey>().unwrap();
 
-                if skip_condition {
-                    if is_add {
-                        println!("no entries; skipping start msg");
-                    } else {
-                        println!("found entries; skipping end msg");
-                    }
+    let entry = channels.entry(id).or_default();
 
-                    return;
-                }
+    debug!(?entry, "Got entry after wait");
 
-                let msgs = if is_add {
-                    config.start_msgs
-                } else {
-                    config.end_msgs
-                };
+    let skip_condition = if is_add {
+        entry.is_empty()
+    } else {
+        !entry.is_empty()
+    };
 
-                let msg_index = rand::thread_rng().gen_range(0..msgs.len());
-                let content = format!("<@&{}> {}", id.0, msgs[msg_index]);
+    if skip_condition {
+        if is_add {
+            debug!("no entries; skipping start msg");
+        } else {
+            debug!("found entries; skipping end msg");
+        }
 
-                #[cfg(debug_assertions)]
-                {
-                    if is_add {
-                        println!("sending start message: {content}");
-                    } else {
-                        println!("sending end message: {content}");
-                    }
-                }
+        return;
+    }
 
-                #[cfg(not(debug_assertions))]
-                {
-                    let res = config
-                        .text_channel_id
-                        .send_message(&http, |msg| {
-                            msg.allowed_mentions(|v| v.roles(vec![id]));
+    let event = WebhookEvent::voice_session(is_add, id, &config.voice_channel_ids, entry.len());
+    webhooks::fire(&data, event);
+    drop(data);
 
-                            msg.content(content);
+    let msgs = if is_add {
+        config.start_msgs
+    } else {
+        config.end_msgs
+    };
 
-                            msg
-                        })
-                        .await;
+    let template = &msgs[rand::thread_rng().gen_range(0..msgs.len())];
+    let mut content = template.render(|name, _| match name {
+        "user" => pending
+            .user_id
+            .map(|user_id| format!("<@{}>", user_id.0))
+            .unwrap_or_default(),
+        "channel" => pending
+            .channel_id
+            .map(|channel_id| format!("<#{}>", channel_id.0))
+            .unwrap_or_default(),
+        "role" => format!("<@&{}>", id.0),
+        _ => String::new(),
+    });
+
+    if !template.uses("role") {
+        content = format!("<@&{}> {}", id.0, content);
+    }
 
-                    if let Err(err) = res {
-                        if is_add {
-                            println!("Got err sending start msg: {err}");
-                        } else {
-                            println!("Got err sending end msg: {err}");
-                        }
-                    }
-                }
+    #[cfg(debug_assertions)]
+    {
+        let _ = http;
+
+        if is_add {
+            info!(%content, "sending start message");
+        } else {
+            info!(%content, "sending end message");
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    {
+        let res = config
+            .text_channel_id
+            .send_message(&http, |msg| {
+                msg.allowed_mentions(|v| v.roles(vec![id]));
+
+                msg.content(content);
+
+                msg
             })
             .await;
+
+        if let Err(err) = res {
+            if is_add {
+                error!(%err, "Got err sending start msg");
+            } else {
+                error!(%err, "Got err sending end msg");
+            }
         }
     }
 }
@@ -326,22 +651,106 @@ async fn handle_vc(ctx: &Context, channel_id: ChannelId, user_id: UserId, is_add
 #[tokio::main]
 async fn main() {
     let config = std::fs::read_to_string("./assets/config.ron").unwrap();
-    let config = ron::from_str::<config::Config>(&config).unwrap();
+    let mut config = ron::from_str::<config::Config>(&config).unwrap();
+    if let Err(err) = config.resolve_docker_engines() {
+        panic!("Invalid config: {}", err);
+    }
+
+    telemetry::init(&config.logging);
+    public_ip::init(&config.public_ip);
+
+    let store = Arc::new(Store::open(&config.state_path).expect("Error opening state store"));
+    let voice_members = store.voice_members().expect("Error loading voice state");
+    for (role_id, members) in &voice_members {
+        metrics::set_voice_members(*role_id, members.len());
+    }
+    let pending_voice_msgs = store
+        .pending_voice_messages()
+        .expect("Error loading pending voice messages");
+
+    let join_redirects = config
+        .web
+        .as_ref()
+        .and_then(|web| web.public_url.as_deref())
+        .map(|public_url| Arc::new(JoinRedirects::new(public_url)));
 
     // Login with a bot token from the environment
     let token = config.discord_token;
     let intents = GatewayIntents::non_privileged();
 
+    let (backup_queue, queued_backups) = tokio::sync::mpsc::unbounded_channel();
+
     let mut client = Client::builder(token, intents)
-        .type_map_insert::<ServerKey>(config.servers)
-        .type_map_insert::<VoiceChatConfigKey>(config.vcs)
-        .type_map_insert::<VoiceChatStateKey>(HashMap::new())
+        .type_map_insert::<ServerKey>(Arc::new(config.servers))
+        .type_map_insert::<VoiceChatConfigKey>(config.vcs.clone())
+        .type_map_insert::<VoiceChatStateKey>(voice_members)
+        .type_map_insert::<ServerStatusKey>(HashMap::new())
+        .type_map_insert::<ExpectedChangesKey>(DashMap::new())
+        .type_map_insert::<AdmissionKey>(config.admission)
+        .type_map_insert::<ServerActivityKey>(DashMap::new())
+        .type_map_insert::<BackupsInProgressKey>(Arc::new(DashSet::new()))
+        .type_map_insert::<BackupQueueKey>(backup_queue)
+        .type_map_insert::<UpdatesInProgressKey>(Arc::new(DashSet::new()))
+        .type_map_insert::<StoreKey>(store.clone())
+        .type_map_insert::<AuditChannelKey>(config.audit_channel_id)
+        .type_map_insert::<WebhooksKey>(Arc::new(Webhooks::new(config.webhooks)))
+        .type_map_insert::<JoinRedirectsKey>(join_redirects)
+        .type_map_insert::<HostsKey>(Arc::new(config.hosts))
         .event_handler(Handler)
         .await
         .expect("Error creating client");
 
+    // resume messages that were waiting to be sent when the bot last stopped
+    for pending in pending_voice_msgs {
+        match config.vcs.get(&pending.role_id) {
+            Some(vc_config) => {
+                tokio::spawn(send_vc_msg(
+                    client.data.clone(),
+                    client.cache_and_http.http.clone(),
+                    vc_config.clone(),
+                    pending,
+                ));
+            }
+            None => {
+                if let Err(err) =
+                    store.remove_pending_voice_message(pending.role_id, pending.is_start)
+                {
+                    error!(%err, "Failed to remove pending voice message");
+                }
+            }
+        }
+    }
+
+    tokio::spawn(notifications::watch_server_statuses(
+        client.data.clone(),
+        client.cache_and_http.http.clone(),
+        Duration::from_secs(config.status_poll_secs),
+    ));
+
+    if let Some(web_config) = config.web {
+        tokio::spawn(web::serve(
+            web_config,
+            client.data.clone(),
+            client.cache_and_http.http.clone(),
+            client.cache_and_http.cache.clone(),
+        ));
+    }
+
+    tokio::spawn(backup::schedule_backups(
+        client.data.clone(),
+        client.cache_and_http.http.clone(),
+    ));
+
+    tokio::spawn(backup::run_queued_backups(
+        client.data.clone(),
+        client.cache_and_http.http.clone(),
+        queued_backups,
+    ));
+
+    tokio::spawn(power::suspend_idle_hosts(client.data.clone()));
+
     // start listening for events by starting a single shard
     if let Err(why) = client.start().await {
-        println!("An error occurred while running the client: {:?}", why);
+        error!(?why, "An error occurred while running the client");
     }
 }
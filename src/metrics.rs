@@ -0,0 +1,168 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    LazyLock,
+};
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use serenity::model::id::RoleId;
+
+use crate::{command::SlashCommand, server_commands::ServerStatus};
+
+const NAMESPACE: &str = "server_bot";
+
+// server commands range from a status check to pulling a whole image
+const OPERATION_BUCKETS: &[f64] = &[
+    0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+const STATUS_KINDS: [&str; 4] = ["running", "stopped", "paused", "unknown"];
+
+// metrics are recorded from server commands, which don't have access to the client data
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+struct Metrics {
+    registry: Registry,
+    commands: IntCounterVec,
+    operation_duration: HistogramVec,
+    server_status: IntGaugeVec,
+    voice_members: IntGaugeVec,
+    gateway_reconnects: IntCounter,
+    connected: AtomicBool,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let commands = IntCounterVec::new(
+            Opts::new(
+                "commands_total",
+                "Slash commands run, by command and result",
+            )
+            .namespace(NAMESPACE),
+            &["command", "result"],
+        )
+        .unwrap();
+        let operation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "operation_duration_seconds",
+                "Duration of server operations, by operation and server type",
+            )
+            .namespace(NAMESPACE)
+            .buckets(OPERATION_BUCKETS.to_vec()),
+            &["operation", "server_type"],
+        )
+        .unwrap();
+        let server_status = IntGaugeVec::new(
+            Opts::new(
+                "server_status",
+                "Last observed status of each server; 1 for the current status",
+            )
+            .namespace(NAMESPACE),
+            &["server", "status"],
+        )
+        .unwrap();
+        let voice_members = IntGaugeVec::new(
+            Opts::new(
+                "voice_channel_members",
+                "Users in the voice channels of each group, by the role pinged for the group",
+            )
+            .namespace(NAMESPACE),
+            &["role"],
+        )
+        .unwrap();
+        let gateway_reconnects = IntCounter::with_opts(
+            Opts::new(
+                "gateway_reconnects_total",
+                "Times the discord gateway connection was resumed or re-established",
+            )
+            .namespace(NAMESPACE),
+        )
+        .unwrap();
+
+        registry.register(Box::new(commands.clone())).unwrap();
+        registry
+            .register(Box::new(operation_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(server_status.clone())).unwrap();
+        registry.register(Box::new(voice_members.clone())).unwrap();
+        registry
+            .register(Box::new(gateway_reconnects.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            commands,
+            operation_duration,
+            server_status,
+            voice_members,
+            gateway_reconnects,
+            connected: AtomicBool::new(false),
+        }
+    }
+}
+
+pub fn record_command(command: SlashCommand, failed: bool) {
+    let result = if failed { "error" } else { "ok" };
+
+    METRICS
+        .commands
+        .with_label_values(&[&command.to_string(), result])
+        .inc();
+}
+
+/// Starts timing a server operation; the duration is recorded when the timer is dropped
+pub fn time_operation(operation: &str, server_type: &str) -> HistogramTimer {
+    METRICS
+        .operation_duration
+        .with_label_values(&[operation, server_type])
+        .start_timer()
+}
+
+pub fn set_server_status(server_name: &str, status: &ServerStatus) {
+    let current = match status {
+        ServerStatus::Running => "running",
+        ServerStatus::Stopped => "stopped",
+        ServerStatus::Paused => "paused",
+        ServerStatus::Unknown(_) => "unknown",
+    };
+
+    for kind in STATUS_KINDS {
+        METRICS
+            .server_status
+            .with_label_values(&[server_name, kind])
+            .set((kind == current) as i64);
+    }
+}
+
+pub fn set_voice_members(role_id: RoleId, members: usize) {
+    METRICS
+        .voice_members
+        .with_label_values(&[&role_id.0.to_string()])
+        .set(members as i64);
+}
+
+/// Records a gateway session being established; every session after the first is a reconnect
+pub fn record_gateway_connect() {
+    if METRICS.connected.swap(true, Ordering::Relaxed) {
+        record_gateway_reconnect();
+    }
+}
+
+pub fn record_gateway_reconnect() {
+    METRICS.gateway_reconnects.inc();
+}
+
+/// Renders every metric in the prometheus text format
+pub fn render() -> String {
+    let mut buffer = vec![];
+
+    if let Err(err) = TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer) {
+        return format!("# failed to encode metrics: {}\n", err);
+    }
+
+    String::from_utf8_lossy(&buffer).into_owned()
+}
@@ -0,0 +1,101 @@
+use std::{
+    io::Write,
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Instant,
+};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, Method, StatusCode, Uri},
+    Router,
+};
+use tempfile::{NamedTempFile, TempPath};
+
+/// A request the mock server got, and when
+pub struct Received {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub at: Instant,
+}
+
+type Respond = dyn Fn(&Received) -> (StatusCode, String) + Send + Sync;
+
+/// A local http server standing in for a service the bot talks to, answering every request
+/// with the given function and recording it
+#[derive(Clone)]
+pub struct MockServer {
+    pub addr: SocketAddr,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+#[derive(Clone)]
+struct Shared {
+    respond: Arc<Respond>,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+async fn handle(
+    State(shared): State<Shared>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, String) {
+    let request = Received {
+        method,
+        uri,
+        headers,
+        body,
+        at: Instant::now(),
+    };
+
+    let response = (shared.respond)(&request);
+    shared.received.lock().unwrap().push(request);
+
+    response
+}
+
+impl MockServer {
+    /// Starts the server on a free local port; has to be called from within a runtime
+    pub fn serve(
+        respond: impl Fn(&Received) -> (StatusCode, String) + Send + Sync + 'static,
+    ) -> Self {
+        let received = Arc::new(Mutex::new(vec![]));
+        let shared = Shared {
+            respond: Arc::new(respond),
+            received: received.clone(),
+        };
+        let app = Router::new().fallback(handle).with_state(shared);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        Self { addr, received }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// The requests received so far, in order
+    pub fn received(&self) -> MutexGuard<'_, Vec<Received>> {
+        self.received.lock().unwrap()
+    }
+}
+
+/// Writes a credential the bot reads from a file, like an api key, removed once dropped
+pub fn secret_file(contents: &str) -> TempPath {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(contents.as_bytes()).unwrap();
+
+    file.into_temp_path()
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use dashmap::DashMap;
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, RoleId},
+    prelude::{RwLock, TypeMap, TypeMapKey},
+};
+
+use tracing::{error, info, warn};
+
+use crate::{
+    audit::AuditActor,
+    backup::BackupsInProgressKey,
+    config::NotificationConfig,
+    metrics,
+    server_commands::{self, ServerCommands, ServerStatus},
+    store::StoreKey,
+    webhooks::{self, WebhookActor, WebhookEvent},
+    ServerKey,
+};
+
+/// Last observed status of each server, used to detect transitions
+pub struct ServerStatusKey;
+impl TypeMapKey for ServerStatusKey {
+    type Value = HashMap<String, ServerStatus>;
+}
+
+/// Bot commands still changing each server's status, counted until the command checks the
+/// result, so the poller doesn't mistake a stop it sees first for a crash
+pub struct ExpectedChangesKey;
+impl TypeMapKey for ExpectedChangesKey {
+    type Value = DashMap<String, usize>;
+}
+
+/// Marks that the bot is about to change the server's status; the check made with the command's
+/// actor afterwards clears the mark
+pub fn expect_change(data: &TypeMap, server_name: &str) {
+    let expected = data.get::<ExpectedChangesKey>().unwrap();
+
+    *expected.entry(server_name.to_string()).or_default() += 1;
+}
+
+// clears one mark made by expect_change, once the command that made it checks the result
+fn settle_change(data: &TypeMap, server_name: &str) {
+    let expected = data.get::<ExpectedChangesKey>().unwrap();
+
+    expected.remove_if_mut(server_name, |_, count| {
+        *count -= 1;
+        *count == 0
+    });
+}
+
+/// Polls every server on an interval, announcing any status transitions,
+/// including those caused outside of the bot.
+pub async fn watch_server_statuses(
+    data: Arc<RwLock<TypeMap>>,
+    http: Arc<Http>,
+    interval: Duration,
+) {
+    loop {
+        let server_names = {
+            let data = data.read().await;
+            let servers = data.get::<ServerKey>().unwrap();
+
+            servers.keys().cloned().collect::<Vec<_>>()
+        };
+
+        for server_name in server_names {
+            check_server_status(&data, &http, &server_name, None).await;
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Checks the current status of a server, and announces it to the server's
+/// notification channel if it changed since the last check.
+pub async fn check_server_status(
+    data: &RwLock<TypeMap>,
+    http: &Http,
+    server_name: &str,
+    actor: Option<AuditActor>,
+) {
+    let servers = data.read().await.get::<ServerKey>().unwrap().clone();
+    let server = match servers.get(server_name) {
+        Some(server) => server,
+        None => return,
+    };
+    let status = server.get_status();
+
+    let status = match status {
+        Ok(status) => status,
+        Err(err) => {
+            warn!(server = %server_name, %err, "Failed to get server status");
+            if actor.is_some() {
+                settle_change(&*data.read().await, server_name);
+            }
+            return;
+        }
+    };
+
+    metrics::set_server_status(server_name, &status);
+
+    // read together with the status, so the poller can't see a change after its mark is cleared
+    let (previous, expected) = {
+        let mut data = data.write().await;
+        if actor.is_some() {
+            settle_change(&data, server_name);
+        }
+        let expected = data
+            .get::<ExpectedChangesKey>()
+            .unwrap()
+            .contains_key(server_name);
+        let statuses = data.get_mut::<ServerStatusKey>().unwrap();
+
+        (
+            statuses.insert(server_name.to_string(), status.clone()),
+            expected,
+        )
+    };
+
+    // the first observation of a server only establishes a baseline
+    let previous = match previous {
+        Some(previous) if !previous.same_kind(&status) => previous,
+        _ => return,
+    };
+
+    info!(server = %server_name, %previous, %status, "Server changed status");
+
+    let store = data.read().await.get::<StoreKey>().unwrap().clone();
+    if let Err(err) = store.record_status(server_name, &status.to_string(), SystemTime::now()) {
+        error!(server = %server_name, %err, "Failed to record server status");
+    }
+
+    {
+        let data = data.read().await;
+
+        webhooks::fire(
+            &data,
+            WebhookEvent::StatusChanged {
+                server: server_name.to_string(),
+                previous_status: previous.to_string(),
+                status: status.to_string(),
+                requested_by: actor.as_ref().map(WebhookActor::from),
+            },
+        );
+
+        // stops by the bot, including those to take a backup, are expected
+        let backing_up = data
+            .get::<BackupsInProgressKey>()
+            .unwrap()
+            .contains(server_name);
+
+        if actor.is_none()
+            && !expected
+            && !backing_up
+            && matches!(status, ServerStatus::Stopped)
+            && matches!(previous, ServerStatus::Running | ServerStatus::Paused)
+        {
+            webhooks::fire(
+                &data,
+                WebhookEvent::Crashed {
+                    server: server_name.to_string(),
+                    previous_status: previous.to_string(),
+                },
+            );
+        }
+    }
+
+    if let Some(config) = server.get_notifications() {
+        send_notification(
+            http,
+            config,
+            server_name,
+            &previous,
+            &status,
+            actor.as_ref(),
+            server.get_query_address(),
+        )
+        .await;
+    }
+}
+
+async fn send_notification(
+    http: &Http,
+    config: &NotificationConfig,
+    server_name: &str,
+    previous: &ServerStatus,
+    status: &ServerStatus,
+    actor: Option<&AuditActor>,
+    query_address: Option<&str>,
+) {
+    let template = match status {
+        ServerStatus::Running => &config.running_msg,
+        ServerStatus::Stopped => &config.stopped_msg,
+        ServerStatus::Paused => &config.paused_msg,
+        ServerStatus::Unknown(_) => &config.unknown_msg,
+    };
+
+    if template.is_empty() {
+        return;
+    }
+
+    let players = if template.uses("players") {
+        server_commands::players(query_address).await
+    } else {
+        String::new()
+    };
+
+    let mut content = template.render(|name, _| match name {
+        "server_name" => server_name.to_string(),
+        "status" => status.to_string(),
+        "previous_status" => previous.to_string(),
+        "user" => actor.map(ToString::to_string).unwrap_or_default(),
+        "role" => config
+            .role_id
+            .map(|role_id| format!("<@&{}>", role_id.0))
+            .unwrap_or_default(),
+        "players" => players.clone(),
+        _ => String::new(),
+    });
+
+    // the requester and role are added unless the message places them itself
+    if let Some(actor) = actor.filter(|_| !template.uses("user")) {
+        content.push_str(&format!(" (requested by {})", actor));
+    }
+
+    if let Some(role_id) = config.role_id.filter(|_| !template.uses("role")) {
+        content = format!("<@&{}> {}", role_id.0, content);
+    }
+
+    post(http, config.channel_id, config.role_id, &content).await;
+}
+
+/// Posts a message to a notification channel, only allowing the given role to be pinged
+pub async fn post(http: &Http, channel_id: ChannelId, role_id: Option<RoleId>, content: &str) {
+    #[cfg(debug_assertions)]
+    {
+        let _ = (http, channel_id, role_id);
+        info!(channel = channel_id.0, %content, "sending notification");
+    }
+
+    #[cfg(not(debug_assertions))]
+    {
+        let res = channel_id
+            .send_message(http, |msg| {
+                msg.allowed_mentions(|v| v.roles(role_id.into_iter().collect::<Vec<_>>()));
+
+                msg.content(content);
+
+                msg
+            })
+            .await;
+
+        if let Err(err) = res {
+            error!(channel = channel_id.0, %err, "Got err sending notification");
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serenity::prelude::{RwLock, TypeMap, TypeMapKey};
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::{error, info};
+
+use crate::{
+    admission::ServerActivityKey,
+    backup::BackupsInProgressKey,
+    command::SlashCommand,
+    config::{DockerEngine, HostConfig, HostMap, MacAddress, ServerType},
+    server_commands::{run_async_command, ServerCommands, ServerError, ServerStatus},
+    ServerKey,
+};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+const ENGINE_TIMEOUT: Duration = Duration::from_secs(10);
+const WAKE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const SUSPEND_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct HostsKey;
+impl TypeMapKey for HostsKey {
+    type Value = Arc<HostMap>;
+}
+
+/// Wakes the host the server runs on, if it has one, and waits until it accepts connections
+pub async fn ensure_awake(
+    data: &RwLock<TypeMap>,
+    server: &ServerType,
+    command: SlashCommand,
+) -> Result<(), ServerError> {
+    let host_name = match server.get_host() {
+        Some(host_name) => host_name,
+        None => return Ok(()),
+    };
+
+    let hosts = data.read().await.get::<HostsKey>().unwrap().clone();
+    let host = hosts.get(host_name).ok_or_else(|| {
+        ServerError::CommandFailed(command, format!("Host {} is not configured", host_name))
+    })?;
+
+    wake(host_name, host)
+        .await
+        .map_err(|err| ServerError::CommandFailed(command, err))
+}
+
+/// Returns true if the host accepts connections on its probe address, or its docker engine responds
+pub async fn probe(host: &HostConfig) -> bool {
+    match (&host.probe_address, &host.docker) {
+        (Some(probe_address), _) => connect(probe_address).await,
+        (None, Some(engine)) => engine_version(engine).await.is_ok(),
+        // nothing to check; assume the host is always on
+        (None, None) => true,
+    }
+}
+
+/// Describes whether the host can be reached, and the version of its docker engine if it has one
+pub async fn health(host: &HostConfig) -> Result<String, String> {
+    match (&host.docker, &host.probe_address) {
+        (Some(engine), _) => engine_version(engine)
+            .await
+            .map(|version| format!("docker {}", version)),
+        (None, Some(probe_address)) => match connect(probe_address).await {
+            true => Ok("reachable".to_string()),
+            false => Err(format!("{} is unreachable", probe_address)),
+        },
+        (None, None) => Ok("not checked".to_string()),
+    }
+}
+
+async fn connect(address: &str) -> bool {
+    matches!(
+        tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(address)).await,
+        Ok(Ok(_))
+    )
+}
+
+async fn engine_version(engine: &DockerEngine) -> Result<String, String> {
+    let mut args = engine.cli_args();
+    args.extend(["version", "--format", "{{.Server.Version}}"].map(String::from));
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    // connecting over ssh can hang for a while on hosts that are down
+    match tokio::time::timeout(
+        ENGINE_TIMEOUT,
+        run_async_command(SlashCommand::List, "docker", &args),
+    )
+    .await
+    {
+        Ok(Ok(version)) => Ok(version.trim().to_string()),
+        Ok(Err(ServerError::CommandFailed(_, err))) => Err(err.trim().to_string()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err("docker engine didn't respond".to_string()),
+    }
+}
+
+async fn wake(host_name: &str, host: &HostConfig) -> Result<(), String> {
+    if probe(host).await {
+        return Ok(());
+    }
+
+    let mac_address = host.mac_address.ok_or_else(|| {
+        format!(
+            "Host {} is unreachable, and has no mac address to wake it with",
+            host_name
+        )
+    })?;
+
+    info!(host = %host_name, "Waking host");
+    send_magic_packet(mac_address, host.wake_address)
+        .await
+        .map_err(|err| format!("Failed to wake host {}: {}", host_name, err))?;
+
+    let deadline = Instant::now() + Duration::from_secs(host.wake_timeout_secs);
+    while Instant::now() < deadline {
+        tokio::time::sleep(WAKE_POLL_INTERVAL).await;
+
+        if probe(host).await {
+            info!(host = %host_name, "Host is awake");
+            return Ok(());
+        }
+    }
+
+    Err(format!(
+        "Host {} didn't wake up within {}s",
+        host_name, host.wake_timeout_secs
+    ))
+}
+
+async fn send_magic_packet(
+    mac_address: MacAddress,
+    wake_address: SocketAddr,
+) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).await?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(&magic_packet(mac_address), wake_address)
+        .await?;
+
+    Ok(())
+}
+
+// six 0xff bytes, then the mac address repeated sixteen times
+fn magic_packet(mac_address: MacAddress) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac_address.0);
+    }
+
+    packet
+}
+
+/// Suspends hosts once all of their servers have been stopped and unused for their idle time
+pub async fn suspend_idle_hosts(data: Arc<RwLock<TypeMap>>) {
+    // when each host was first seen awake with every server stopped
+    let mut idle_since = HashMap::new();
+
+    loop {
+        tokio::time::sleep(SUSPEND_CHECK_INTERVAL).await;
+
+        let (hosts, servers) = {
+            let data = data.read().await;
+
+            (
+                data.get::<HostsKey>().unwrap().clone(),
+                data.get::<ServerKey>().unwrap().clone(),
+            )
+        };
+
+        for (host_name, host) in hosts.iter() {
+            let suspend = match &host.suspend {
+                Some(suspend) => suspend,
+                None => continue,
+            };
+
+            let host_servers = servers
+                .iter()
+                .filter(|(_, server)| server.get_host() == Some(host_name.as_str()))
+                .collect::<Vec<_>>();
+
+            if host_servers.is_empty() || !probe(host).await {
+                idle_since.remove(host_name);
+                continue;
+            }
+
+            if !all_stopped(&data, &host_servers).await {
+                idle_since.remove(host_name);
+                continue;
+            }
+
+            let idle_for = Duration::from_secs(suspend.idle_mins * 60);
+            let since = *idle_since
+                .entry(host_name.clone())
+                .or_insert_with(Instant::now);
+
+            if since.elapsed() < idle_for || used_within(&data, &host_servers, idle_for).await {
+                continue;
+            }
+
+            // checked again right before suspending, since reading the statuses takes a while
+            // and a server may have been started in the meantime
+            if !all_stopped(&data, &host_servers).await
+                || used_within(&data, &host_servers, idle_for).await
+            {
+                idle_since.remove(host_name);
+                continue;
+            }
+
+            info!(host = %host_name, "Suspending idle host");
+            idle_since.remove(host_name);
+
+            if let Err(err) = suspend.command.run_async(SlashCommand::Stop).await {
+                error!(host = %host_name, %err, "Failed to suspend host");
+            }
+        }
+    }
+}
+
+/// Returns true if every server is stopped and not being backed up
+async fn all_stopped(data: &RwLock<TypeMap>, servers: &[(&String, &ServerType)]) -> bool {
+    // copied out, so the data lock isn't held while statuses are read over the network
+    let backups = data
+        .read()
+        .await
+        .get::<BackupsInProgressKey>()
+        .unwrap()
+        .clone();
+
+    servers.iter().all(|(server_name, server)| {
+        !backups.contains(*server_name) && matches!(server.get_status(), Ok(ServerStatus::Stopped))
+    })
+}
+
+// a server that was just used is likely to be started again soon
+async fn used_within(
+    data: &RwLock<TypeMap>,
+    servers: &[(&String, &ServerType)],
+    duration: Duration,
+) -> bool {
+    let data = data.read().await;
+    let activity = data.get::<ServerActivityKey>().unwrap();
+
+    servers
+        .iter()
+        .filter_map(|(server_name, _)| activity.get(*server_name).map(|time| *time))
+        .any(|time| {
+            SystemTime::now()
+                .duration_since(time)
+                .is_ok_and(|age| age < duration)
+        })
+}
@@ -0,0 +1,544 @@
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use serenity::async_trait;
+
+use crate::{
+    command::SlashCommand,
+    config::PterodactylServer,
+    server_commands::{
+        block_on, render_connect, render_endpoints, ServerCommands, ServerError, ServerStatus,
+    },
+    stats::ResourceUsage,
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const STOP_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The `resources` endpoint's response
+#[derive(Deserialize)]
+struct Stats {
+    attributes: StatsAttributes,
+}
+
+#[derive(Deserialize)]
+struct StatsAttributes {
+    // offline, starting, running or stopping
+    current_state: String,
+    #[serde(default)]
+    is_suspended: bool,
+    resources: Resources,
+}
+
+#[derive(Deserialize)]
+struct Resources {
+    memory_bytes: u64,
+    // where 100 is one full core, like docker
+    cpu_absolute: f64,
+    network_rx_bytes: u64,
+    network_tx_bytes: u64,
+    // milliseconds, or 0 while offline
+    #[serde(default)]
+    uptime: u64,
+}
+
+impl PterodactylServer {
+    fn client(&self) -> Result<&reqwest::Client, String> {
+        self.client
+            .get_or_init(|| {
+                reqwest::Client::builder()
+                    .timeout(REQUEST_TIMEOUT)
+                    .build()
+                    .map_err(|err| err.to_string())
+            })
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+
+    /// Sends a request to the panel's client api for this server, returning the response body
+    async fn request(
+        &self,
+        command: SlashCommand,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<String, ServerError> {
+        let failed = |err: String| ServerError::CommandFailed(command, err);
+
+        let client = self.client().map_err(failed)?;
+
+        // errors mention the file, never its contents
+        let api_key = fs::read_to_string(&self.api_key_file).map_err(|err| {
+            failed(format!(
+                "Failed to read api key file {}: {}",
+                self.api_key_file.display(),
+                err
+            ))
+        })?;
+
+        let mut request = client
+            .request(
+                method,
+                format!(
+                    "{}/api/client/servers/{}{}",
+                    self.panel_url.trim_end_matches('/'),
+                    self.server_id,
+                    path
+                ),
+            )
+            .bearer_auth(api_key.trim())
+            .header(reqwest::header::ACCEPT, "application/json");
+
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| failed(err.without_url().to_string()))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(failed(panel_error(status, &body)))
+        }
+    }
+
+    async fn power(&self, command: SlashCommand, signal: &str) -> Result<(), ServerError> {
+        self.request(
+            command,
+            Method::POST,
+            "/power",
+            Some(json!({ "signal": signal })),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn stats(&self, command: SlashCommand) -> Result<StatsAttributes, ServerError> {
+        let body = self
+            .request(command, Method::GET, "/resources", None)
+            .await?;
+
+        serde_json::from_str::<Stats>(&body)
+            .map(|stats| stats.attributes)
+            .map_err(|err| ServerError::CommandFailed(command, err.to_string()))
+    }
+
+    async fn status(&self) -> Result<ServerStatus, ServerError> {
+        let stats = self.stats(SlashCommand::Status).await?;
+
+        Ok(match stats.current_state.as_str() {
+            _ if stats.is_suspended => ServerStatus::Unknown("suspended".to_string()),
+            "running" => ServerStatus::Running,
+            "offline" => ServerStatus::Stopped,
+            state => ServerStatus::Unknown(state.to_string()),
+        })
+    }
+
+    /// Asks the server to stop, checking its state every poll interval and killing it if it
+    /// doesn't stop in time. A server that's already stopping is only waited on, and killed
+    /// if it's stuck.
+    async fn stop(&self, poll_interval: Duration) -> Result<(), ServerError> {
+        if self.stats(SlashCommand::Stop).await?.current_state != "stopping" {
+            self.power(SlashCommand::Stop, "stop").await?;
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(self.stop_timeout_secs);
+        loop {
+            if self.stats(SlashCommand::Stop).await?.current_state == "offline" {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+
+            tokio::time::sleep(poll_interval).await;
+        }
+
+        self.power(SlashCommand::Stop, "kill").await
+    }
+}
+
+#[async_trait]
+impl ServerCommands for PterodactylServer {
+    async fn connect(
+        &self,
+        server_name: &str,
+        endpoint: Option<&str>,
+    ) -> Result<String, ServerError> {
+        render_endpoints(&self.connect, endpoint, |template| {
+            render_connect(template, server_name, self.query_address.as_deref(), |_| {
+                String::new()
+            })
+        })
+        .await
+    }
+
+    async fn join_link(&self, server_name: &str) -> Result<Option<String>, ServerError> {
+        match &self.join_link {
+            Some(link) => {
+                let rendered = render_connect(
+                    link.template(),
+                    server_name,
+                    self.query_address.as_deref(),
+                    |_| String::new(),
+                )
+                .await?;
+                Ok(Some(link.link(&rendered)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn start_server(&self) -> Result<(), ServerError> {
+        block_on(self.power(SlashCommand::Start, "start"))
+    }
+
+    fn stop_server(&self) -> Result<(), ServerError> {
+        block_on(self.stop(STOP_POLL_INTERVAL))
+    }
+
+    fn restart_server(&self) -> Result<(), ServerError> {
+        block_on(self.power(SlashCommand::Restart, "restart"))
+    }
+
+    fn get_status(&self) -> Result<ServerStatus, ServerError> {
+        block_on(self.status())
+    }
+
+    async fn get_resource_usage(&self) -> Result<ResourceUsage, ServerError> {
+        let resources = self.stats(SlashCommand::Status).await?.resources;
+
+        Ok(ResourceUsage {
+            cpu_percent: Some(resources.cpu_absolute),
+            memory_used: Some(resources.memory_bytes),
+            memory_limit: None,
+            network_rx: Some(resources.network_rx_bytes),
+            network_tx: Some(resources.network_tx_bytes),
+            uptime: Some(Duration::from_millis(resources.uptime)),
+        })
+    }
+}
+
+// the panel describes errors as a list of {code, status, detail}
+fn panel_error(status: StatusCode, body: &str) -> String {
+    let details = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|body| {
+            body["errors"].as_array().map(|errors| {
+                errors
+                    .iter()
+                    .filter_map(|error| error["detail"].as_str())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            })
+        })
+        .filter(|details| !details.is_empty());
+
+    match details {
+        Some(details) => format!("Panel responded with {}: {}", status, details),
+        None => format!("Panel responded with {}", status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, OnceLock};
+
+    use axum::http::header;
+    use tempfile::TempPath;
+
+    use super::*;
+    use crate::{
+        config::ServerType,
+        mock_http::{secret_file, MockServer, Received},
+    };
+
+    const SERVER_PATH: &str = "/api/client/servers/1a7ce997";
+    const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+    #[derive(Clone, Default)]
+    struct Panel {
+        // the server's current_state, e.g. running or offline
+        state: Arc<Mutex<String>>,
+        suspended: bool,
+        // whether a stopping server stops, rather than being stuck stopping
+        stops: bool,
+        // how many times a stopping server reports it's still stopping before it's offline
+        stopping_polls: Arc<Mutex<usize>>,
+    }
+
+    fn respond(panel: &Panel, request: &Received) -> (StatusCode, String) {
+        let path = request
+            .uri
+            .path()
+            .strip_prefix(SERVER_PATH)
+            .unwrap_or_default();
+        let mut state = panel.state.lock().unwrap();
+
+        match (&request.method, path) {
+            (&Method::GET, "/resources") => {
+                if *state == "stopping" && panel.stops {
+                    let mut stopping_polls = panel.stopping_polls.lock().unwrap();
+                    match *stopping_polls {
+                        0 => *state = "offline".to_string(),
+                        _ => *stopping_polls -= 1,
+                    }
+                }
+
+                let stats = json!({
+                    "object": "stats",
+                    "attributes": {
+                        "current_state": *state,
+                        "is_suspended": panel.suspended,
+                        "resources": {
+                            "memory_bytes": 524288000,
+                            "cpu_absolute": 37.5,
+                            "disk_bytes": 1073741824,
+                            "network_rx_bytes": 1234,
+                            "network_tx_bytes": 5678,
+                            "uptime": 3723000,
+                        },
+                    },
+                });
+
+                (StatusCode::OK, stats.to_string())
+            }
+            (&Method::POST, "/power") => {
+                *state = match signal(request).as_str() {
+                    "start" | "restart" => "running",
+                    "stop" => "stopping",
+                    "kill" => "offline",
+                    _ => return (StatusCode::UNPROCESSABLE_ENTITY, String::new()),
+                }
+                .to_string();
+
+                (StatusCode::NO_CONTENT, String::new())
+            }
+            _ => {
+                let errors = json!({
+                    "errors": [{
+                        "code": "NotFoundHttpException",
+                        "status": "404",
+                        "detail": "The requested resource could not be found on the server.",
+                    }]
+                });
+
+                (StatusCode::NOT_FOUND, errors.to_string())
+            }
+        }
+    }
+
+    fn serve(panel: &Panel) -> MockServer {
+        let panel = panel.clone();
+
+        MockServer::serve(move |request| respond(&panel, request))
+    }
+
+    fn panel(state: &str) -> Panel {
+        Panel {
+            state: Arc::new(Mutex::new(state.to_string())),
+            stops: true,
+            ..Default::default()
+        }
+    }
+
+    fn signal(request: &Received) -> String {
+        serde_json::from_slice::<Value>(&request.body).unwrap()["signal"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    // the power signals the panel got, in order
+    fn signals(mock: &MockServer) -> Vec<String> {
+        mock.received()
+            .iter()
+            .filter(|request| request.method == Method::POST)
+            .map(signal)
+            .collect()
+    }
+
+    // the api key file is removed once its path is dropped
+    fn server(mock: &MockServer, stop_timeout_secs: u64) -> (PterodactylServer, TempPath) {
+        let api_key_file = secret_file("ptlc_test\n");
+        let server = PterodactylServer {
+            panel_url: mock.url("/"),
+            server_id: "1a7ce997".to_string(),
+            api_key_file: api_key_file.to_path_buf(),
+            stop_timeout_secs,
+            connect: ron::from_str("\"{public_ip}:25565\"").unwrap(),
+            guild_ids: Default::default(),
+            notifications: None,
+            log_redactions: vec![],
+            memory_weight: 0,
+            query_address: None,
+            join_link: None,
+            client: OnceLock::new(),
+        };
+
+        (server, api_key_file)
+    }
+
+    async fn status(panel: Panel) -> ServerStatus {
+        let (server, _api_key_file) = server(&serve(&panel), 0);
+
+        server.status().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn maps_resources_to_status() {
+        assert_eq!(status(panel("running")).await, ServerStatus::Running);
+        assert_eq!(status(panel("offline")).await, ServerStatus::Stopped);
+        assert_eq!(
+            status(panel("starting")).await,
+            ServerStatus::Unknown("starting".to_string())
+        );
+        assert_eq!(
+            status(Panel {
+                suspended: true,
+                ..panel("running")
+            })
+            .await,
+            ServerStatus::Unknown("suspended".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_resource_usage() {
+        let mock = serve(&panel("running"));
+        let (server, _api_key_file) = server(&mock, 0);
+
+        let usage = server.get_resource_usage().await.unwrap();
+        assert_eq!(usage.cpu_percent, Some(37.5));
+        assert_eq!(usage.memory_used, Some(524288000));
+        assert_eq!(usage.network_rx, Some(1234));
+        assert_eq!(usage.network_tx, Some(5678));
+        assert_eq!(usage.uptime, Some(Duration::from_secs(3723)));
+
+        let received = mock.received();
+        assert_eq!(received.len(), 1);
+        assert_eq!(
+            received[0].headers[header::AUTHORIZATION],
+            "Bearer ptlc_test"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_power_signals() {
+        let mock = serve(&panel("offline"));
+        let (server, _api_key_file) = server(&mock, 5);
+
+        server.start_server().unwrap();
+        server.restart_server().unwrap();
+        server.stop_server().unwrap();
+
+        assert_eq!(signals(&mock), ["start", "restart", "stop"]);
+    }
+
+    #[tokio::test]
+    async fn waits_for_server_to_stop() {
+        let panel = Panel {
+            stopping_polls: Arc::new(Mutex::new(3)),
+            ..panel("running")
+        };
+        let mock = serve(&panel);
+        let (server, _api_key_file) = server(&mock, 5);
+
+        server.stop(POLL_INTERVAL).await.unwrap();
+
+        assert_eq!(signals(&mock), ["stop"]);
+        assert_eq!(*panel.state.lock().unwrap(), "offline");
+    }
+
+    #[tokio::test]
+    async fn kills_server_that_does_not_stop() {
+        let panel = Panel {
+            stops: false,
+            ..panel("running")
+        };
+        let mock = serve(&panel);
+        let (server, _api_key_file) = server(&mock, 0);
+
+        server.stop(POLL_INTERVAL).await.unwrap();
+
+        assert_eq!(signals(&mock), ["stop", "kill"]);
+        assert_eq!(*panel.state.lock().unwrap(), "offline");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn kills_server_stuck_stopping() {
+        let mock = serve(&Panel {
+            stops: false,
+            ..panel("stopping")
+        });
+        let (server, _api_key_file) = server(&mock, 0);
+
+        ServerType::Pterodactyl(server).stop_server().unwrap();
+
+        assert_eq!(signals(&mock), ["kill"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stops_server_stuck_starting() {
+        let mock = serve(&panel("starting"));
+        let (server, _api_key_file) = server(&mock, 5);
+
+        ServerType::Pterodactyl(server).stop_server().unwrap();
+
+        assert_eq!(signals(&mock), ["stop"]);
+    }
+
+    #[tokio::test]
+    async fn reports_panel_errors() {
+        let (mut server, _api_key_file) = server(&serve(&panel("running")), 0);
+        server.server_id = "missing".to_string();
+
+        let err = server.status().await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ServerError::CommandFailed(
+                SlashCommand::Status,
+                "Panel responded with 404 Not Found: The requested resource could not be found on the server."
+                    .to_string()
+            )
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn describes_panel_errors() {
+        let body = json!({
+            "errors": [
+                { "code": "ConflictHttpException", "status": "409", "detail": "Server is busy." },
+                { "code": "BadRequestHttpException", "status": "400", "detail": "Try later." },
+            ]
+        })
+        .to_string();
+
+        assert_eq!(
+            panel_error(StatusCode::CONFLICT, &body),
+            "Panel responded with 409 Conflict: Server is busy.; Try later."
+        );
+        assert_eq!(
+            panel_error(StatusCode::BAD_GATEWAY, "<html>Bad gateway</html>"),
+            "Panel responded with 502 Bad Gateway"
+        );
+        assert_eq!(
+            panel_error(StatusCode::FORBIDDEN, r#"{"errors": []}"#),
+            "Panel responded with 403 Forbidden"
+        );
+    }
+}
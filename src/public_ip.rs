@@ -0,0 +1,330 @@
+use std::{
+    net::IpAddr,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use serenity::async_trait;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::config::{IpProviderConfig, PublicIpConfig};
+
+// connect strings are resolved from server commands, which don't have access to the client data
+static RESOLVER: OnceLock<PublicIpResolver> = OnceLock::new();
+
+/// A source of the host's public ip
+#[async_trait]
+pub trait IpProvider: Send + Sync {
+    async fn public_ip(&self) -> Result<IpAddr, String>;
+
+    /// Describes the provider in errors and logs
+    fn name(&self) -> String;
+}
+
+/// Resolves the public ip from the first provider that succeeds, caching it for a while
+pub struct PublicIpResolver {
+    providers: Vec<Box<dyn IpProvider>>,
+    ttl: Duration,
+    timeout: Duration,
+    // held while resolving, so concurrent lookups share one result
+    cached: Mutex<Option<(IpAddr, Instant)>>,
+}
+
+impl PublicIpResolver {
+    pub fn new(providers: Vec<Box<dyn IpProvider>>, ttl: Duration, timeout: Duration) -> Self {
+        Self {
+            providers,
+            ttl,
+            timeout,
+            cached: Mutex::new(None),
+        }
+    }
+
+    pub fn from_config(config: &PublicIpConfig) -> Self {
+        let providers = config
+            .providers
+            .iter()
+            .cloned()
+            .map(|provider| Box::new(provider) as Box<dyn IpProvider>)
+            .collect();
+
+        Self::new(
+            providers,
+            Duration::from_secs(config.ttl_secs),
+            Duration::from_secs(config.timeout_secs),
+        )
+    }
+
+    pub async fn resolve(&self) -> Result<IpAddr, String> {
+        let mut cached = self.cached.lock().await;
+
+        if let Some((ip, resolved_at)) = *cached {
+            if resolved_at.elapsed() < self.ttl {
+                return Ok(ip);
+            }
+        }
+
+        let mut errors = vec![];
+
+        for provider in &self.providers {
+            let result = match tokio::time::timeout(self.timeout, provider.public_ip()).await {
+                Ok(result) => result,
+                Err(_) => Err("timed out".to_string()),
+            };
+
+            match result {
+                Ok(ip) => {
+                    debug!(provider = %provider.name(), %ip, "Resolved public ip");
+                    *cached = Some((ip, Instant::now()));
+                    return Ok(ip);
+                }
+                Err(err) => {
+                    warn!(provider = %provider.name(), %err, "Failed to resolve public ip");
+                    errors.push(format!("{}: {}", provider.name(), err));
+                }
+            }
+        }
+
+        // an outdated ip is more likely to be right than no ip at all
+        if let Some((ip, _)) = *cached {
+            warn!(%ip, "Using expired public ip");
+            return Ok(ip);
+        }
+
+        if errors.is_empty() {
+            return Err("No public ip providers are configured".to_string());
+        }
+
+        Err(errors.join("; "))
+    }
+}
+
+/// Sets up the resolver used for connect strings; uses the default providers if never called
+pub fn init(config: &PublicIpConfig) {
+    if RESOLVER.set(PublicIpResolver::from_config(config)).is_err() {
+        warn!("Public ip resolver was already initialized");
+    }
+}
+
+pub async fn resolve() -> Result<IpAddr, String> {
+    RESOLVER
+        .get_or_init(|| PublicIpResolver::from_config(&PublicIpConfig::default()))
+        .resolve()
+        .await
+}
+
+#[async_trait]
+impl IpProvider for IpProviderConfig {
+    async fn public_ip(&self) -> Result<IpAddr, String> {
+        match self {
+            IpProviderConfig::Static(ip) => Ok(*ip),
+            IpProviderConfig::Http(url) => {
+                let body = reqwest::get(url)
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|err| err.to_string())?
+                    .text()
+                    .await
+                    .map_err(|err| err.to_string())?;
+
+                body.trim()
+                    .parse()
+                    .map_err(|_| format!("Response is not an ip: {:?}", body.trim()))
+            }
+            IpProviderConfig::Dns(host) => {
+                let addresses = tokio::net::lookup_host((host.as_str(), 0))
+                    .await
+                    .map_err(|err| err.to_string())?
+                    .map(|address| address.ip())
+                    .collect::<Vec<_>>();
+
+                preferred_ip(addresses).ok_or_else(|| "No addresses found".to_string())
+            }
+            IpProviderConfig::Interface(name) => {
+                let addresses = if_addrs::get_if_addrs()
+                    .map_err(|err| err.to_string())?
+                    .into_iter()
+                    .filter(|interface| &interface.name == name && !interface.is_link_local())
+                    .map(|interface| interface.ip())
+                    .collect::<Vec<_>>();
+
+                preferred_ip(addresses).ok_or_else(|| "No addresses found".to_string())
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            IpProviderConfig::Static(ip) => format!("static {}", ip),
+            IpProviderConfig::Http(url) => format!("http {}", url),
+            IpProviderConfig::Dns(host) => format!("dns {}", host),
+            IpProviderConfig::Interface(name) => format!("interface {}", name),
+        }
+    }
+}
+
+// most games only accept ipv4 in connect strings
+fn preferred_ip(addresses: Vec<IpAddr>) -> Option<IpAddr> {
+    addresses
+        .iter()
+        .find(|ip| ip.is_ipv4())
+        .or(addresses.first())
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        net::Ipv4Addr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+    const OTHER_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    /// Answers with the given results in order, repeating the last one
+    struct MockProvider {
+        name: &'static str,
+        results: std::sync::Mutex<VecDeque<Result<IpAddr, String>>>,
+        delay: Duration,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl MockProvider {
+        fn instant(
+            name: &'static str,
+            results: Vec<Result<IpAddr, String>>,
+        ) -> (Box<dyn IpProvider>, Arc<AtomicUsize>) {
+            Self::delayed(name, results, Duration::ZERO)
+        }
+
+        fn delayed(
+            name: &'static str,
+            results: Vec<Result<IpAddr, String>>,
+            delay: Duration,
+        ) -> (Box<dyn IpProvider>, Arc<AtomicUsize>) {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let provider = Self {
+                name,
+                results: std::sync::Mutex::new(results.into()),
+                delay,
+                calls: calls.clone(),
+            };
+
+            (Box::new(provider), calls)
+        }
+    }
+
+    #[async_trait]
+    impl IpProvider for MockProvider {
+        async fn public_ip(&self) -> Result<IpAddr, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+
+            let mut results = self.results.lock().unwrap();
+            match results.len() {
+                1 => results[0].clone(),
+                _ => results.pop_front().unwrap(),
+            }
+        }
+
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn reuses_ip_within_ttl() {
+        let (provider, calls) = MockProvider::instant("mock", vec![Ok(IP), Ok(OTHER_IP)]);
+        let resolver = PublicIpResolver::new(vec![provider], HOUR, HOUR);
+
+        assert_eq!(resolver.resolve().await, Ok(IP));
+        assert_eq!(resolver.resolve().await, Ok(IP));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn resolves_again_after_ttl() {
+        let (provider, calls) = MockProvider::instant("mock", vec![Ok(IP), Ok(OTHER_IP)]);
+        let resolver = PublicIpResolver::new(vec![provider], Duration::ZERO, HOUR);
+
+        assert_eq!(resolver.resolve().await, Ok(IP));
+        assert_eq!(resolver.resolve().await, Ok(OTHER_IP));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn falls_back_in_order() {
+        let (failing, failing_calls) =
+            MockProvider::instant("failing", vec![Err("down".to_string())]);
+        let (working, working_calls) = MockProvider::instant("working", vec![Ok(IP)]);
+        let (unused, unused_calls) = MockProvider::instant("unused", vec![Ok(OTHER_IP)]);
+        let resolver = PublicIpResolver::new(vec![failing, working, unused], HOUR, HOUR);
+
+        assert_eq!(resolver.resolve().await, Ok(IP));
+        assert_eq!(failing_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(working_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(unused_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn times_out_slow_providers() {
+        let (slow, _) = MockProvider::delayed("slow", vec![Ok(OTHER_IP)], HOUR);
+        let (fast, _) = MockProvider::instant("fast", vec![Ok(IP)]);
+        let resolver = PublicIpResolver::new(vec![slow, fast], HOUR, Duration::from_millis(50));
+
+        let started = Instant::now();
+        assert_eq!(resolver.resolve().await, Ok(IP));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn reports_every_failure() {
+        let (failing, _) = MockProvider::instant("failing", vec![Err("down".to_string())]);
+        let (slow, _) = MockProvider::delayed("slow", vec![Ok(IP)], HOUR);
+        let resolver = PublicIpResolver::new(vec![failing, slow], HOUR, Duration::from_millis(50));
+
+        assert_eq!(
+            resolver.resolve().await,
+            Err("failing: down; slow: timed out".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn uses_expired_ip_when_every_provider_fails() {
+        let (provider, calls) =
+            MockProvider::instant("mock", vec![Ok(IP), Err("down".to_string())]);
+        let resolver = PublicIpResolver::new(vec![provider], Duration::ZERO, HOUR);
+
+        assert_eq!(resolver.resolve().await, Ok(IP));
+        assert_eq!(resolver.resolve().await, Ok(IP));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn fails_without_providers() {
+        let resolver = PublicIpResolver::new(vec![], HOUR, HOUR);
+
+        assert_eq!(
+            resolver.resolve().await,
+            Err("No public ip providers are configured".to_string())
+        );
+    }
+
+    #[test]
+    fn prefers_ipv4() {
+        let ipv6 = "2001:db8::1".parse().unwrap();
+
+        assert_eq!(preferred_ip(vec![ipv6, IP]), Some(IP));
+        assert_eq!(preferred_ip(vec![ipv6]), Some(ipv6));
+        assert_eq!(preferred_ip(vec![]), None);
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::net::UdpSocket;
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+const PACKET_HEADER: [u8; 4] = [0xFF; 4];
+const A2S_INFO: &[u8] = b"TSource Engine Query\0";
+const INFO_RESPONSE: u8 = 0x49;
+const CHALLENGE_RESPONSE: u8 = 0x41;
+
+/// Players on a server, as reported by the steam server query protocol
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlayerCount {
+    pub players: u8,
+    pub max_players: u8,
+}
+
+/// Queries the player count of a server with an A2S_INFO request to its query address
+pub async fn query_players(address: &str) -> Result<PlayerCount, Error> {
+    match tokio::time::timeout(QUERY_TIMEOUT, query_info(address)).await {
+        Ok(result) => result,
+        Err(_) => Err(Error::new(ErrorKind::TimedOut, "Server query timed out")),
+    }
+}
+
+async fn query_info(address: &str) -> Result<PlayerCount, Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(address).await?;
+
+    let mut request = [&PACKET_HEADER[..], A2S_INFO].concat();
+    let mut buffer = [0; 1400];
+
+    // servers may answer with a challenge first, which has to be appended to the request
+    for _ in 0..2 {
+        socket.send(&request).await?;
+        let len = socket.recv(&mut buffer).await?;
+
+        match parse_response(&buffer[..len])? {
+            Response::Info(count) => return Ok(count),
+            Response::Challenge(challenge) => {
+                request.truncate(PACKET_HEADER.len() + A2S_INFO.len());
+                request.extend_from_slice(&challenge);
+            }
+        }
+    }
+
+    Err(invalid("Server kept sending challenges"))
+}
+
+enum Response {
+    Info(PlayerCount),
+    Challenge([u8; 4]),
+}
+
+fn parse_response(packet: &[u8]) -> Result<Response, Error> {
+    let packet = packet
+        .strip_prefix(&PACKET_HEADER)
+        .ok_or_else(|| invalid("Unsupported response packet"))?;
+
+    match packet.split_first() {
+        Some((&CHALLENGE_RESPONSE, challenge)) => challenge
+            .get(..4)
+            .and_then(|challenge| challenge.try_into().ok())
+            .map(Response::Challenge)
+            .ok_or_else(|| invalid("Truncated challenge")),
+        Some((&INFO_RESPONSE, info)) => parse_info(info).map(Response::Info),
+        _ => Err(invalid("Unexpected response type")),
+    }
+}
+
+fn parse_info(info: &[u8]) -> Result<PlayerCount, Error> {
+    // protocol version, then the name, map, folder and game strings
+    let mut rest = info.get(1..).ok_or_else(|| invalid("Truncated info"))?;
+    for _ in 0..4 {
+        let end = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| invalid("Truncated info"))?;
+        rest = &rest[end + 1..];
+    }
+
+    // then the steam app id, followed by the player counts
+    match rest.get(2..4) {
+        Some(&[players, max_players]) => Ok(PlayerCount {
+            players,
+            max_players,
+        }),
+        _ => Err(invalid("Truncated info")),
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
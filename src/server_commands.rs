@@ -1,19 +1,40 @@
 use serenity::async_trait;
 
 use crate::{
+    admission::CapacityError,
     command::SlashCommand,
-    config::{Docker, ServerType},
+    config::{
+        CommandDefinition, Connect, CustomServer, Docker, DockerEngine, LogSource, ServerMap,
+        ServerType,
+    },
+    logs::{self, LogQuery, LogStream},
+    metrics, public_ip, query,
+    stats::{self, ResourceUsage},
+    template::{DockerConnectVars, Template},
+    update::{self, UpdateSummary},
 };
+use serde::Deserialize;
 use std::{
+    collections::{BTreeMap, HashMap},
     error::Error,
     fmt::{Display, Formatter},
+    future::Future,
     process,
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
+use tokio::runtime::Handle;
+
+// remote docker engines are reached over the network; queries should answer quickly, while
+// changes like stopping wait for the container to exit
+const REMOTE_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+const REMOTE_CHANGE_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub enum ServerError {
     StatusError(ServerStatus, SlashCommand),
     CommandFailed(SlashCommand, String),
+    InsufficientCapacity(CapacityError),
 }
 
 impl Display for ServerError {
@@ -25,13 +46,14 @@ impl Display for ServerError {
                 command, status
             ),
             ServerError::CommandFailed(cmd, err) => write!(f, "Command {} failed: {}", cmd, err),
+            ServerError::InsufficientCapacity(err) => write!(f, "{}", err),
         }
     }
 }
 
 impl Error for ServerError {}
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ServerStatus {
     Running,
     Stopped,
@@ -40,15 +62,73 @@ pub enum ServerStatus {
     Unknown(String),
 }
 
+impl ServerStatus {
+    /// Returns true if both statuses are the same kind, ignoring any unknown status details
+    pub fn same_kind(&self, other: &ServerStatus) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl Display for ServerStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerStatus::Running => write!(f, "Running"),
+            ServerStatus::Stopped => write!(f, "Stopped"),
+            ServerStatus::Paused => write!(f, "Paused"),
+            ServerStatus::Unknown(status) => write!(f, "Unknown ({})", status),
+        }
+    }
+}
+
+/// A container port published on the host
+#[derive(Debug, Clone)]
+pub struct PublishedPort {
+    /// The port inside the container, with its protocol, e.g. "27015/udp"
+    pub container_port: String,
+    pub host_ip: String,
+    pub host_port: String,
+}
+
+impl PublishedPort {
+    /// Returns true if this is the given container port; without a protocol, any protocol matches
+    fn matches(&self, container_port: &str) -> bool {
+        match container_port.contains('/') {
+            true => self.container_port == container_port,
+            false => self
+                .container_port
+                .split_once('/')
+                .is_some_and(|(port, _)| port == container_port),
+        }
+    }
+}
+
+impl Display for PublishedPort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.host_ip.as_str() {
+            "" | "0.0.0.0" | "::" => write!(f, "{} -> {}", self.container_port, self.host_port),
+            ip => write!(f, "{} -> {}:{}", self.container_port, ip, self.host_port),
+        }
+    }
+}
+
 #[async_trait]
 pub trait ServerCommands {
-    /// Returns the connect string for the server
-    async fn connect(&self) -> Result<String, ServerError>;
+    /// Returns the connect strings for the server, or only the one for the named endpoint
+    async fn connect(
+        &self,
+        server_name: &str,
+        endpoint: Option<&str>,
+    ) -> Result<String, ServerError>;
+
+    /// Returns a link that joins the server from the game, if one is configured
+    async fn join_link(&self, _server_name: &str) -> Result<Option<String>, ServerError> {
+        Ok(None)
+    }
 
     /// Starts the server if it is not already running
     fn start_server(&self) -> Result<(), ServerError>;
 
-    /// Stops the server if it is running
+    /// Stops the server unless it is already stopped
     fn stop_server(&self) -> Result<(), ServerError>;
 
     /// Restarts the server if it is running; otherwise starts it
@@ -90,211 +170,990 @@ pub trait ServerCommands {
 
     /// Gets the status of the server
     fn get_status(&self) -> Result<ServerStatus, ServerError>;
+
+    /// Gets the most recent logs of the server
+    fn get_logs(&self, _query: &LogQuery) -> Result<String, ServerError> {
+        Err(ServerError::CommandFailed(
+            SlashCommand::Logs,
+            "Logs are not available for this server".to_string(),
+        ))
+    }
+
+    /// Streams new logs of the server as they are written
+    fn follow_logs(&self) -> Result<LogStream, ServerError> {
+        Err(ServerError::CommandFailed(
+            SlashCommand::Logs,
+            "Following logs is not supported for this server".to_string(),
+        ))
+    }
+
+    /// Gets the CPU, memory, and network usage of the server
+    async fn get_resource_usage(&self) -> Result<ResourceUsage, ServerError> {
+        Err(ServerError::CommandFailed(
+            SlashCommand::Status,
+            "Resource usage is not available for this server".to_string(),
+        ))
+    }
+
+    /// Updates the server to its newest version
+    async fn update_server(&self) -> Result<UpdateSummary, ServerError> {
+        Err(ServerError::CommandFailed(
+            SlashCommand::Update,
+            "Updating is not supported for this server".to_string(),
+        ))
+    }
+
+    /// Gets the ports the server publishes on the host, if it knows them
+    async fn get_ports(&self) -> Result<Vec<PublishedPort>, ServerError> {
+        Ok(vec![])
+    }
+}
+
+impl ServerType {
+    /// Gets the status from the backend without timing it, so checks made inside a timed
+    /// operation aren't also recorded as status operations
+    fn backend_status(&self) -> Result<ServerStatus, ServerError> {
+        match self {
+            ServerType::Docker(docker) => docker.get_status(),
+            ServerType::Custom(custom) => custom.get_status(),
+            ServerType::Kubernetes(kubernetes) => kubernetes.get_status(),
+            ServerType::Pterodactyl(pterodactyl) => pterodactyl.get_status(),
+        }
+    }
 }
 
 #[async_trait]
 impl ServerCommands for ServerType {
-    async fn connect(&self) -> Result<String, ServerError> {
-        match self.get_status() {
+    async fn connect(
+        &self,
+        server_name: &str,
+        endpoint: Option<&str>,
+    ) -> Result<String, ServerError> {
+        let _timer = metrics::time_operation("connect", self.get_type_name());
+
+        match self.backend_status() {
+            Ok(ServerStatus::Running) => match self {
+                ServerType::Docker(docker) => docker.connect(server_name, endpoint).await,
+                ServerType::Custom(custom) => custom.connect(server_name, endpoint).await,
+                ServerType::Kubernetes(kubernetes) => {
+                    kubernetes.connect(server_name, endpoint).await
+                }
+                ServerType::Pterodactyl(pterodactyl) => {
+                    pterodactyl.connect(server_name, endpoint).await
+                }
+            },
+            Ok(status) => Err(ServerError::StatusError(status, SlashCommand::Connect)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn join_link(&self, server_name: &str) -> Result<Option<String>, ServerError> {
+        match self.backend_status() {
             Ok(ServerStatus::Running) => match self {
-                ServerType::Docker(docker) => docker.connect().await,
+                ServerType::Docker(docker) => docker.join_link(server_name).await,
+                ServerType::Custom(custom) => custom.join_link(server_name).await,
+                ServerType::Kubernetes(kubernetes) => kubernetes.join_link(server_name).await,
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.join_link(server_name).await,
             },
-            Ok(status) => return Err(ServerError::StatusError(status, SlashCommand::Connect)),
-            Err(e) => return Err(e),
+            Ok(status) => Err(ServerError::StatusError(status, SlashCommand::Connect)),
+            Err(e) => Err(e),
         }
     }
 
     fn start_server(&self) -> Result<(), ServerError> {
-        match self.get_status() {
+        let _timer = metrics::time_operation("start", self.get_type_name());
+
+        match self.backend_status() {
             Ok(ServerStatus::Stopped) => match self {
                 ServerType::Docker(docker) => docker.start_server(),
+                ServerType::Custom(custom) => custom.start_server(),
+                ServerType::Kubernetes(kubernetes) => kubernetes.start_server(),
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.start_server(),
             },
-            Ok(status) => return Err(ServerError::StatusError(status, SlashCommand::Start)),
-            Err(e) => return Err(e),
+            Ok(status) => Err(ServerError::StatusError(status, SlashCommand::Start)),
+            Err(e) => Err(e),
         }
     }
 
     fn stop_server(&self) -> Result<(), ServerError> {
-        match self.get_status() {
-            Ok(ServerStatus::Running) => match self {
+        let _timer = metrics::time_operation("stop", self.get_type_name());
+
+        // servers stuck starting, stopping or crash looping can be stopped too
+        match self.backend_status() {
+            Ok(ServerStatus::Stopped) => Err(ServerError::StatusError(
+                ServerStatus::Stopped,
+                SlashCommand::Stop,
+            )),
+            Ok(_) => match self {
                 ServerType::Docker(docker) => docker.stop_server(),
+                ServerType::Custom(custom) => custom.stop_server(),
+                ServerType::Kubernetes(kubernetes) => kubernetes.stop_server(),
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.stop_server(),
             },
-            Ok(status) => return Err(ServerError::StatusError(status, SlashCommand::Stop)),
-            Err(e) => return Err(e),
+            Err(e) => Err(e),
         }
     }
 
     fn restart_server(&self) -> Result<(), ServerError> {
-        match self.get_status() {
-            Ok(ServerStatus::Stopped) => self.start_server(),
+        let _timer = metrics::time_operation("restart", self.get_type_name());
+
+        match self.backend_status() {
+            Ok(ServerStatus::Stopped) => match self {
+                ServerType::Docker(docker) => docker.start_server(),
+                ServerType::Custom(custom) => custom.start_server(),
+                ServerType::Kubernetes(kubernetes) => kubernetes.start_server(),
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.start_server(),
+            },
             Ok(_) => match self {
                 ServerType::Docker(docker) => docker.restart_server(),
+                ServerType::Custom(custom) => custom.restart_server(),
+                ServerType::Kubernetes(kubernetes) => kubernetes.restart_server(),
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.restart_server(),
             },
-            Err(e) => return Err(e),
+            Err(e) => Err(e),
         }
     }
 
     fn pause_server(&self) -> Result<(), ServerError> {
-        match self.get_status() {
+        let _timer = metrics::time_operation("pause", self.get_type_name());
+
+        match self.backend_status() {
             Ok(ServerStatus::Running) => match self {
                 ServerType::Docker(docker) => docker.pause_server(),
+                ServerType::Custom(custom) => custom.pause_server(),
+                ServerType::Kubernetes(kubernetes) => kubernetes.pause_server(),
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.pause_server(),
             },
-            Ok(status) => return Err(ServerError::StatusError(status, SlashCommand::Pause)),
-            Err(e) => return Err(e),
+            Ok(status) => Err(ServerError::StatusError(status, SlashCommand::Pause)),
+            Err(e) => Err(e),
         }
     }
 
     fn unpause_server(&self) -> Result<(), ServerError> {
-        match self.get_status() {
+        let _timer = metrics::time_operation("unpause", self.get_type_name());
+
+        match self.backend_status() {
             Ok(ServerStatus::Paused) => match self {
                 ServerType::Docker(docker) => docker.unpause_server(),
+                ServerType::Custom(custom) => custom.unpause_server(),
+                ServerType::Kubernetes(kubernetes) => kubernetes.unpause_server(),
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.unpause_server(),
             },
-            Ok(status) => return Err(ServerError::StatusError(status, SlashCommand::Unpause)),
-            Err(e) => return Err(e),
+            Ok(status) => Err(ServerError::StatusError(status, SlashCommand::Unpause)),
+            Err(e) => Err(e),
         }
     }
 
     fn resume_server(&self) -> Result<(), ServerError> {
-        match self.get_status() {
+        let _timer = metrics::time_operation("resume", self.get_type_name());
+
+        match self.backend_status() {
             Ok(ServerStatus::Paused | ServerStatus::Stopped) => match self {
                 ServerType::Docker(docker) => docker.resume_server(),
+                ServerType::Custom(custom) => custom.resume_server(),
+                ServerType::Kubernetes(kubernetes) => kubernetes.resume_server(),
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.resume_server(),
             },
-            Ok(status) => return Err(ServerError::StatusError(status, SlashCommand::Resume)),
-            Err(e) => return Err(e),
+            Ok(status) => Err(ServerError::StatusError(status, SlashCommand::Resume)),
+            Err(e) => Err(e),
         }
     }
 
     fn get_status(&self) -> Result<ServerStatus, ServerError> {
+        let _timer = metrics::time_operation("status", self.get_type_name());
+
+        self.backend_status()
+    }
+
+    fn get_logs(&self, query: &LogQuery) -> Result<String, ServerError> {
+        let _timer = metrics::time_operation("logs", self.get_type_name());
+
+        let logs = match self {
+            ServerType::Docker(docker) => docker.get_logs(query),
+            ServerType::Custom(custom) => custom.get_logs(query),
+            ServerType::Kubernetes(kubernetes) => kubernetes.get_logs(query),
+            ServerType::Pterodactyl(pterodactyl) => pterodactyl.get_logs(query),
+        }?;
+
+        let redactions = logs::compile_redactions(self.get_log_redactions()).map_err(|err| {
+            ServerError::CommandFailed(
+                SlashCommand::Logs,
+                format!("Invalid log redaction pattern: {}", err),
+            )
+        })?;
+
+        Ok(logs::redact(&logs, &redactions))
+    }
+
+    fn follow_logs(&self) -> Result<LogStream, ServerError> {
         match self {
-            ServerType::Docker(docker) => docker.get_status(),
+            ServerType::Docker(docker) => docker.follow_logs(),
+            ServerType::Custom(custom) => custom.follow_logs(),
+            ServerType::Kubernetes(kubernetes) => kubernetes.follow_logs(),
+            ServerType::Pterodactyl(pterodactyl) => pterodactyl.follow_logs(),
+        }
+    }
+
+    async fn get_resource_usage(&self) -> Result<ResourceUsage, ServerError> {
+        let _timer = metrics::time_operation("resource_usage", self.get_type_name());
+
+        match self.backend_status() {
+            Ok(ServerStatus::Running) => match self {
+                ServerType::Docker(docker) => docker.get_resource_usage().await,
+                ServerType::Custom(custom) => custom.get_resource_usage().await,
+                ServerType::Kubernetes(kubernetes) => kubernetes.get_resource_usage().await,
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.get_resource_usage().await,
+            },
+            Ok(status) => Err(ServerError::StatusError(status, SlashCommand::Status)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update_server(&self) -> Result<UpdateSummary, ServerError> {
+        let _timer = metrics::time_operation("update", self.get_type_name());
+
+        match self.backend_status() {
+            Ok(ServerStatus::Running | ServerStatus::Stopped) => match self {
+                ServerType::Docker(docker) => docker.update_server().await,
+                ServerType::Custom(custom) => custom.update_server().await,
+                ServerType::Kubernetes(kubernetes) => kubernetes.update_server().await,
+                ServerType::Pterodactyl(pterodactyl) => pterodactyl.update_server().await,
+            },
+            Ok(status) => Err(ServerError::StatusError(status, SlashCommand::Update)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_ports(&self) -> Result<Vec<PublishedPort>, ServerError> {
+        match self {
+            ServerType::Docker(docker) => docker.get_ports().await,
+            ServerType::Custom(custom) => custom.get_ports().await,
+            ServerType::Kubernetes(kubernetes) => kubernetes.get_ports().await,
+            ServerType::Pterodactyl(pterodactyl) => pterodactyl.get_ports().await,
         }
     }
 }
 
-#[async_trait]
-impl ServerCommands for Docker {
-    async fn connect(&self) -> Result<String, ServerError> {
-        Ok(if self.connect.contains("$PUBLIC_IP") {
-            let public_ip = reqwest::get("https://api.ipify.org")
+impl Docker {
+    fn engine_args(&self) -> Vec<String> {
+        self.engine
+            .as_ref()
+            .map(DockerEngine::cli_args)
+            .unwrap_or_default()
+    }
+
+    /// The docker cli arguments for a command, run against the engine of the server's host
+    fn cli_args(&self, args: &[&str]) -> Vec<String> {
+        let mut cli_args = self.engine_args();
+        cli_args.extend(args.iter().map(|arg| arg.to_string()));
+        cli_args
+    }
+
+    /// Runs a docker command without blocking, giving up after the timeout on remote engines,
+    /// which can hang for a while when their host is down
+    async fn docker_output(
+        &self,
+        command: SlashCommand,
+        args: &[&str],
+        remote_timeout: Duration,
+    ) -> Result<process::Output, ServerError> {
+        let args = self.cli_args(args);
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        let output = run_async_output(command, "docker", &args);
+
+        match self.engine {
+            Some(_) => tokio::time::timeout(remote_timeout, output)
                 .await
                 .map_err(|_| {
                     ServerError::CommandFailed(
-                        SlashCommand::Connect,
-                        "Failed to get public IP".to_string(),
+                        command,
+                        format!(
+                            "Docker engine didn't respond within {}s",
+                            remote_timeout.as_secs()
+                        ),
                     )
-                })?
-                .text()
-                .await
-                .map_err(|_| {
+                })?,
+            None => output.await,
+        }
+    }
+
+    /// Runs a docker command without blocking, returning its stdout if it succeeded
+    async fn docker(&self, command: SlashCommand, args: &[&str]) -> Result<String, ServerError> {
+        let output = self
+            .docker_output(command, args, REMOTE_QUERY_TIMEOUT)
+            .await?;
+
+        command_stdout(command, output)
+    }
+
+    /// Runs a docker command that changes the container's state, from a synchronous server command
+    fn change(&self, command: SlashCommand, args: &[&str]) -> Result<(), ServerError> {
+        block_on(self.docker_output(command, args, REMOTE_CHANGE_TIMEOUT))
+            .and_then(|output| command_stdout(command, output))
+            .map(|_| ())
+    }
+
+    /// Fills in a connect string or join link, looking up the published ports it uses
+    async fn render(
+        &self,
+        template: &Template<DockerConnectVars>,
+        server_name: &str,
+    ) -> Result<String, ServerError> {
+        let published = match template.uses("port") {
+            true => self.published_ports(SlashCommand::Connect).await?,
+            false => vec![],
+        };
+
+        let mut ports = HashMap::new();
+        for container_port in template.arguments("port") {
+            // without a container port, the first one published is used
+            let port = published
+                .iter()
+                .find(|port| {
+                    container_port.is_none_or(|container_port| port.matches(container_port))
+                })
+                .ok_or_else(|| {
                     ServerError::CommandFailed(
                         SlashCommand::Connect,
-                        "Failed to get public IP".to_string(),
+                        format!(
+                            "Container {} doesn't publish port {}",
+                            self.container_name,
+                            container_port.unwrap_or("any")
+                        ),
                     )
                 })?;
+            ports.insert(container_port, port.host_port.clone());
+        }
 
-            self.connect.replace("$PUBLIC_IP", &public_ip)
-        } else {
-            self.connect.clone()
+        render_connect(
+            template,
+            server_name,
+            self.query_address.as_deref(),
+            |port| ports.get(&port).cloned().unwrap_or_default(),
+        )
+        .await
+    }
+
+    /// Reads the container's port bindings from `NetworkSettings.Ports`
+    async fn published_ports(
+        &self,
+        command: SlashCommand,
+    ) -> Result<Vec<PublishedPort>, ServerError> {
+        let output = self
+            .docker(
+                command,
+                &[
+                    "inspect",
+                    "--format",
+                    "{{json .NetworkSettings.Ports}}",
+                    &self.container_name,
+                ],
+            )
+            .await?;
+
+        // container ports map to their host bindings, or null if they are exposed but not published
+        let bindings: Option<BTreeMap<String, Option<Vec<PortBinding>>>> =
+            serde_json::from_str(output.trim())
+                .map_err(|err| ServerError::CommandFailed(command, err.to_string()))?;
+
+        let mut ports = bindings
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|(container_port, bindings)| {
+                bindings
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(move |binding| PublishedPort {
+                        container_port: container_port.clone(),
+                        host_ip: binding.host_ip,
+                        host_port: binding.host_port,
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        // docker binds ipv4 and ipv6 separately; the same host port only needs listing once
+        ports.dedup_by(|a, b| a.container_port == b.container_port && a.host_port == b.host_port);
+
+        Ok(ports)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PortBinding {
+    host_ip: String,
+    host_port: String,
+}
+
+#[async_trait]
+impl ServerCommands for Docker {
+    async fn connect(
+        &self,
+        server_name: &str,
+        endpoint: Option<&str>,
+    ) -> Result<String, ServerError> {
+        render_endpoints(&self.connect, endpoint, |template| {
+            self.render(template, server_name)
         })
+        .await
+    }
+
+    async fn join_link(&self, server_name: &str) -> Result<Option<String>, ServerError> {
+        match &self.join_link {
+            Some(link) => {
+                let rendered = self.render(link.template(), server_name).await?;
+                Ok(Some(link.link(&rendered)))
+            }
+            None => Ok(None),
+        }
     }
 
     fn start_server(&self) -> Result<(), ServerError> {
-        let cmd = process::Command::new("docker")
-            .args(&["start", &self.container_name])
-            .output()
-            .expect("Failed to start server");
-
-        cmd.status
-            .success()
-            .then(|| ())
-            .ok_or(ServerError::CommandFailed(
-                SlashCommand::Start,
-                String::from_utf8(cmd.stderr).expect("Failed to read stderr"),
-            ))
+        self.change(SlashCommand::Start, &["start", &self.container_name])
     }
 
     fn stop_server(&self) -> Result<(), ServerError> {
-        let cmd = process::Command::new("docker")
-            .args(&["stop", &self.container_name])
-            .output()
-            .expect("Failed to stop server");
-
-        cmd.status
-            .success()
-            .then(|| ())
-            .ok_or(ServerError::CommandFailed(
-                SlashCommand::Stop,
-                String::from_utf8(cmd.stderr).expect("Failed to read stderr"),
-            ))
+        self.change(SlashCommand::Stop, &["stop", &self.container_name])
     }
 
     fn restart_server(&self) -> Result<(), ServerError> {
-        let cmd = process::Command::new("docker")
-            .args(&["restart", &self.container_name])
-            .output()
-            .expect("Failed to restart server");
-
-        cmd.status
-            .success()
-            .then(|| ())
-            .ok_or(ServerError::CommandFailed(
-                SlashCommand::Restart,
-                String::from_utf8(cmd.stderr).expect("Failed to read stderr"),
-            ))
+        self.change(SlashCommand::Restart, &["restart", &self.container_name])
     }
 
     fn pause_server(&self) -> Result<(), ServerError> {
-        let cmd = process::Command::new("docker")
-            .args(&["pause", &self.container_name])
-            .output()
-            .expect("Failed to pause server");
-
-        cmd.status
-            .success()
-            .then(|| ())
-            .ok_or(ServerError::CommandFailed(
-                SlashCommand::Pause,
-                String::from_utf8(cmd.stderr).expect("Failed to read stderr"),
-            ))
+        self.change(SlashCommand::Pause, &["pause", &self.container_name])
     }
 
     fn unpause_server(&self) -> Result<(), ServerError> {
-        let cmd = process::Command::new("docker")
-            .args(&["unpause", &self.container_name])
-            .output()
-            .expect("Failed to pause server");
-
-        cmd.status
-            .success()
-            .then(|| ())
-            .ok_or(ServerError::CommandFailed(
-                SlashCommand::Unpause,
-                String::from_utf8(cmd.stderr).expect("Failed to read stderr"),
-            ))
+        self.change(SlashCommand::Unpause, &["unpause", &self.container_name])
     }
 
     fn get_status(&self) -> Result<ServerStatus, ServerError> {
-        let cmd = process::Command::new("docker")
-            .args(&["inspect", &self.container_name])
-            .output()
-            .expect("Failed to get server status");
+        let status = block_on(self.docker(
+            SlashCommand::Status,
+            &[
+                "inspect",
+                "--format",
+                "{{.State.Status}}",
+                &self.container_name,
+            ],
+        ))?;
+
+        match status.trim() {
+            "running" => Ok(ServerStatus::Running),
+            "paused" => Ok(ServerStatus::Paused),
+            "exited" => Ok(ServerStatus::Stopped),
+            status => Ok(ServerStatus::Unknown(status.to_string())),
+        }
+    }
+
+    fn get_logs(&self, query: &LogQuery) -> Result<String, ServerError> {
+        let mut args = vec![
+            "logs".to_string(),
+            "--timestamps".to_string(),
+            "--tail".to_string(),
+            query.lines.to_string(),
+        ];
+
+        if let Some(since) = query.since {
+            args.push("--since".to_string());
+            args.push(format!("{}s", since.as_secs()));
+        }
+
+        args.push(self.container_name.clone());
+
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        let cmd = block_on(self.docker_output(SlashCommand::Logs, &args, REMOTE_QUERY_TIMEOUT))?;
 
         if cmd.status.success() {
-            let status = String::from_utf8(cmd.stdout).expect("Failed to read stdout");
-            if status.contains("\"Status\": \"running\"") {
-                Ok(ServerStatus::Running)
-            } else if status.contains("\"Status\": \"paused\"") {
-                Ok(ServerStatus::Paused)
-            } else if status.contains("\"Status\": \"exited\"") {
-                Ok(ServerStatus::Stopped)
-            } else {
-                Ok(ServerStatus::Unknown(status))
-            }
+            // docker splits the container's stdout and stderr; interleave them again by timestamp
+            let stdout = String::from_utf8_lossy(&cmd.stdout);
+            let stderr = String::from_utf8_lossy(&cmd.stderr);
+            let mut lines = stdout.lines().chain(stderr.lines()).collect::<Vec<_>>();
+
+            lines.sort_by(|a, b| {
+                let a = a.split_once(' ').map(|(timestamp, _)| timestamp);
+                let b = b.split_once(' ').map(|(timestamp, _)| timestamp);
+                a.cmp(&b)
+            });
+
+            Ok(logs::tail_lines(&lines.join("\n"), query.lines))
         } else {
             Err(ServerError::CommandFailed(
+                SlashCommand::Logs,
+                String::from_utf8_lossy(&cmd.stderr).into_owned(),
+            ))
+        }
+    }
+
+    fn follow_logs(&self) -> Result<LogStream, ServerError> {
+        // `docker logs --follow` exits on its own when the container stops
+        LogStream::spawn(
+            "docker",
+            &self.cli_args(&["logs", "--follow", "--tail", "0", &self.container_name]),
+        )
+        .map_err(|err| ServerError::CommandFailed(SlashCommand::Logs, err.to_string()))
+    }
+
+    async fn get_resource_usage(&self) -> Result<ResourceUsage, ServerError> {
+        let stats = self
+            .docker(
+                SlashCommand::Status,
+                &[
+                    "stats",
+                    "--no-stream",
+                    "--format",
+                    "{{json .}}",
+                    &self.container_name,
+                ],
+            )
+            .await?;
+        let stats: serde_json::Value = serde_json::from_str(&stats)
+            .map_err(|err| ServerError::CommandFailed(SlashCommand::Status, err.to_string()))?;
+
+        let field = |name: &str| stats.get(name).and_then(|value| value.as_str());
+        let pair = |name: &str| {
+            field(name)
+                .and_then(|value| value.split_once('/'))
+                .map_or((None, None), |(a, b)| {
+                    (stats::parse_size(a), stats::parse_size(b))
+                })
+        };
+
+        let cpu_percent = field("CPUPerc").and_then(|cpu| cpu.trim_end_matches('%').parse().ok());
+        let (memory_used, memory_limit) = pair("MemUsage");
+        let (network_rx, network_tx) = pair("NetIO");
+
+        let started_at = self
+            .docker(
+                SlashCommand::Status,
+                &[
+                    "inspect",
+                    "--format",
+                    "{{.State.StartedAt}}",
+                    &self.container_name,
+                ],
+            )
+            .await?;
+        let uptime = humantime::parse_rfc3339(started_at.trim())
+            .ok()
+            .and_then(|started_at| SystemTime::now().duration_since(started_at).ok());
+
+        Ok(ResourceUsage {
+            cpu_percent,
+            memory_used,
+            memory_limit,
+            network_rx,
+            network_tx,
+            uptime,
+        })
+    }
+
+    async fn get_ports(&self) -> Result<Vec<PublishedPort>, ServerError> {
+        self.published_ports(SlashCommand::Status).await
+    }
+
+    async fn update_server(&self) -> Result<UpdateSummary, ServerError> {
+        update::update_container(&self.engine_args(), &self.container_name).await
+    }
+}
+
+#[async_trait]
+impl ServerCommands for CustomServer {
+    async fn connect(
+        &self,
+        server_name: &str,
+        endpoint: Option<&str>,
+    ) -> Result<String, ServerError> {
+        render_endpoints(&self.connect, endpoint, |template| {
+            render_connect(template, server_name, self.query_address.as_deref(), |_| {
+                String::new()
+            })
+        })
+        .await
+    }
+
+    async fn join_link(&self, server_name: &str) -> Result<Option<String>, ServerError> {
+        match &self.join_link {
+            Some(link) => {
+                let rendered = render_connect(
+                    link.template(),
+                    server_name,
+                    self.query_address.as_deref(),
+                    |_| String::new(),
+                )
+                .await?;
+                Ok(Some(link.link(&rendered)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn start_server(&self) -> Result<(), ServerError> {
+        self.start.run(SlashCommand::Start).map(|_| ())
+    }
+
+    fn stop_server(&self) -> Result<(), ServerError> {
+        self.stop.run(SlashCommand::Stop).map(|_| ())
+    }
+
+    fn restart_server(&self) -> Result<(), ServerError> {
+        match &self.restart {
+            Some(restart) => restart.run(SlashCommand::Restart).map(|_| ()),
+            None => self.stop_server().and_then(|_| self.start_server()),
+        }
+    }
+
+    fn pause_server(&self) -> Result<(), ServerError> {
+        match &self.pause {
+            Some(pause) => pause.run(SlashCommand::Pause).map(|_| ()),
+            None => self.stop_server(),
+        }
+    }
+
+    fn unpause_server(&self) -> Result<(), ServerError> {
+        match &self.unpause {
+            Some(unpause) => unpause.run(SlashCommand::Unpause).map(|_| ()),
+            None => self.start_server(),
+        }
+    }
+
+    fn get_status(&self) -> Result<ServerStatus, ServerError> {
+        // status commands may exit with an error for stopped servers (e.g. `systemctl is-active`),
+        // so only the output is checked
+        let output = spawn_command(&self.status.cmd, &self.status.args, SlashCommand::Status)?;
+        let status = String::from_utf8_lossy(&output.stdout);
+        let status = status.trim();
+
+        if status == self.status.running_status {
+            Ok(ServerStatus::Running)
+        } else if status == self.status.paused_status {
+            Ok(ServerStatus::Paused)
+        } else if status == self.status.stopped_status {
+            Ok(ServerStatus::Stopped)
+        } else if status == self.status.pending_status {
+            Ok(ServerStatus::Unknown("pending".to_string()))
+        } else {
+            Ok(ServerStatus::Unknown(status.to_string()))
+        }
+    }
+
+    fn get_logs(&self, query: &LogQuery) -> Result<String, ServerError> {
+        if query.since.is_some() {
+            return Err(ServerError::CommandFailed(
+                SlashCommand::Logs,
+                "Filtering logs by time is not supported for this server".to_string(),
+            ));
+        }
+
+        match &self.logs {
+            Some(LogSource::File(path)) => logs::tail_file(path, query.lines)
+                .map_err(|err| ServerError::CommandFailed(SlashCommand::Logs, err.to_string())),
+            Some(LogSource::Command(command)) => command
+                .run(SlashCommand::Logs)
+                .map(|logs| logs::tail_lines(&logs, query.lines)),
+            None => Err(ServerError::CommandFailed(
+                SlashCommand::Logs,
+                "Logs are not configured for this server".to_string(),
+            )),
+        }
+    }
+
+    fn follow_logs(&self) -> Result<LogStream, ServerError> {
+        match &self.logs {
+            Some(LogSource::File(path)) => LogStream::spawn(
+                "tail",
+                &[
+                    "--follow=name".to_string(),
+                    "--retry".to_string(),
+                    "--lines=0".to_string(),
+                    path.to_string_lossy().into_owned(),
+                ],
+            )
+            .map_err(|err| ServerError::CommandFailed(SlashCommand::Logs, err.to_string())),
+            Some(LogSource::Command(_)) => Err(ServerError::CommandFailed(
+                SlashCommand::Logs,
+                "Following logs is only supported for log files".to_string(),
+            )),
+            None => Err(ServerError::CommandFailed(
+                SlashCommand::Logs,
+                "Logs are not configured for this server".to_string(),
+            )),
+        }
+    }
+
+    async fn get_resource_usage(&self) -> Result<ResourceUsage, ServerError> {
+        let unit = self.systemd_unit.as_ref().ok_or_else(|| {
+            ServerError::CommandFailed(
                 SlashCommand::Status,
-                String::from_utf8(cmd.stderr).expect("Failed to read stderr"),
+                "Resource usage requires a systemd unit for this server".to_string(),
+            )
+        })?;
+
+        let properties = run_async_command(
+            SlashCommand::Status,
+            "systemctl",
+            &[
+                "show",
+                unit,
+                "--property=ControlGroup,ActiveEnterTimestampMonotonic",
+            ],
+        )
+        .await?;
+
+        let property = |name: &str| {
+            properties.lines().find_map(|line| {
+                line.strip_prefix(name)
+                    .and_then(|line| line.strip_prefix('='))
+            })
+        };
+
+        let cgroup = property("ControlGroup").unwrap_or_default();
+        let active_since_boot = property("ActiveEnterTimestampMonotonic")
+            .and_then(|usecs| usecs.parse().ok())
+            .map(Duration::from_micros)
+            .unwrap_or_default();
+
+        stats::read_cgroup_usage(cgroup, active_since_boot)
+            .await
+            .map_err(|err| ServerError::CommandFailed(SlashCommand::Status, err.to_string()))
+    }
+
+    async fn update_server(&self) -> Result<UpdateSummary, ServerError> {
+        let update = self.update.as_ref().ok_or_else(|| {
+            ServerError::CommandFailed(
+                SlashCommand::Update,
+                "No update command is configured for this server".to_string(),
+            )
+        })?;
+
+        let previous_version = self.read_version().await?;
+        update.run_async(SlashCommand::Update).await?;
+        let current_version = self.read_version().await?;
+
+        // without a version command there's no telling whether anything changed
+        let updated = previous_version.is_none() || previous_version != current_version;
+
+        Ok(UpdateSummary {
+            previous_version,
+            current_version,
+            updated,
+        })
+    }
+}
+
+impl CustomServer {
+    async fn read_version(&self) -> Result<Option<String>, ServerError> {
+        match &self.version {
+            Some(version) => Ok(Some(
+                version
+                    .run_async(SlashCommand::Update)
+                    .await?
+                    .trim()
+                    .to_string(),
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+impl CommandDefinition {
+    /// Runs the command, returning its stdout if it succeeded
+    fn run(&self, command: SlashCommand) -> Result<String, ServerError> {
+        let output = spawn_command(&self.cmd, &self.args, command)?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(ServerError::CommandFailed(
+                command,
+                String::from_utf8_lossy(&output.stderr).into_owned(),
             ))
         }
     }
+
+    /// Runs the command without blocking, returning its stdout if it succeeded
+    pub async fn run_async(&self, command: SlashCommand) -> Result<String, ServerError> {
+        let args = self.args.iter().map(String::as_str).collect::<Vec<_>>();
+
+        run_async_command(command, &self.cmd, &args).await
+    }
+}
+
+fn spawn_command(
+    cmd: &str,
+    args: &[String],
+    command: SlashCommand,
+) -> Result<process::Output, ServerError> {
+    process::Command::new(cmd)
+        .args(args)
+        .output()
+        .map_err(|err| ServerError::CommandFailed(command, err.to_string()))
+}
+
+/// Runs a command without blocking, returning its stdout if it succeeded
+pub async fn run_async_command(
+    command: SlashCommand,
+    cmd: &str,
+    args: &[&str],
+) -> Result<String, ServerError> {
+    let output = run_async_output(command, cmd, args).await?;
+
+    command_stdout(command, output)
+}
+
+/// Runs a command without blocking, returning its output whether or not it succeeded.
+/// The command is killed if the future is dropped, e.g. when it times out.
+async fn run_async_output(
+    command: SlashCommand,
+    cmd: &str,
+    args: &[&str],
+) -> Result<process::Output, ServerError> {
+    tokio::process::Command::new(cmd)
+        .args(args)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|err| ServerError::CommandFailed(command, err.to_string()))
+}
+
+fn command_stdout(command: SlashCommand, output: process::Output) -> Result<String, ServerError> {
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(ServerError::CommandFailed(
+            command,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+/// Renders every endpoint at once, or only the one that was asked for.
+/// Endpoints that fail are reported next to the others, unless all of them fail.
+pub async fn render_endpoints<'a, V, F, Fut>(
+    connect: &'a Connect<V>,
+    endpoint: Option<&str>,
+    render: F,
+) -> Result<String, ServerError>
+where
+    F: Fn(&'a Template<V>) -> Fut,
+    Fut: Future<Output = Result<String, ServerError>>,
+{
+    let endpoints = connect.endpoints();
+
+    if let Some(endpoint) = endpoint {
+        return match endpoints
+            .iter()
+            .find(|(name, _)| name.is_some_and(|name| name.eq_ignore_ascii_case(endpoint)))
+        {
+            Some((_, template)) => render(template).await,
+            None => Err(ServerError::CommandFailed(
+                SlashCommand::Connect,
+                match endpoints
+                    .iter()
+                    .filter_map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                {
+                    names if names.is_empty() => {
+                        "This server doesn't have named endpoints".to_string()
+                    }
+                    names => format!(
+                        "Unknown endpoint {}; expected one of {}",
+                        endpoint,
+                        names.join(", ")
+                    ),
+                },
+            )),
+        };
+    }
+
+    let results =
+        futures::future::join_all(endpoints.iter().map(|(_, template)| render(template))).await;
+
+    if let [(None, _)] = endpoints.as_slice() {
+        return results.into_iter().next().unwrap();
+    }
+
+    if results.iter().all(Result::is_err) {
+        return results.into_iter().next().unwrap_or_else(|| {
+            Err(ServerError::CommandFailed(
+                SlashCommand::Connect,
+                "No connect endpoints are configured".to_string(),
+            ))
+        });
+    }
+
+    Ok(endpoints
+        .iter()
+        .zip(results)
+        .map(|((name, _), result)| match result {
+            Ok(connect) => format!("**{}**: {}", name.unwrap_or_default(), connect),
+            Err(err) => format!("**{}**: unavailable ({})", name.unwrap_or_default(), err),
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Runs async code from a synchronous server command, e.g. to call a backend's http api
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| Handle::current().block_on(future))
+}
+
+/// Gets the status of a server on a blocking thread, so the statuses of several servers can be
+/// read at once
+pub async fn status_of(
+    servers: Arc<ServerMap>,
+    server_name: String,
+) -> Result<ServerStatus, ServerError> {
+    tokio::task::spawn_blocking(move || servers[&server_name].get_status())
+        .await
+        .map_err(|err| ServerError::CommandFailed(SlashCommand::Status, err.to_string()))?
+}
+
+/// Fills in the connect string, only resolving the variables it uses
+pub async fn render_connect<V>(
+    template: &Template<V>,
+    server_name: &str,
+    query_address: Option<&str>,
+    port: impl Fn(Option<&str>) -> String,
+) -> Result<String, ServerError> {
+    let public_ip = if template.uses("public_ip") {
+        let public_ip = public_ip::resolve().await.map_err(|err| {
+            ServerError::CommandFailed(
+                SlashCommand::Connect,
+                format!("Failed to get public IP: {}", err),
+            )
+        })?;
+
+        Some(public_ip.to_string())
+    } else {
+        None
+    };
+
+    let players = if template.uses("players") {
+        Some(players(query_address).await)
+    } else {
+        None
+    };
+
+    Ok(template.render(|name, arg| match name {
+        "public_ip" => public_ip.clone().unwrap_or_default(),
+        "port" => port(arg),
+        "server_name" => server_name.to_string(),
+        "players" => players.clone().unwrap_or_default(),
+        _ => String::new(),
+    }))
+}
+
+/// Describes the players on the server, like "3/16", or "?" if they can't be queried
+pub async fn players(query_address: Option<&str>) -> String {
+    let address = match query_address {
+        Some(address) => address,
+        None => return "?".to_string(),
+    };
+
+    match query::query_players(address).await {
+        Ok(count) => format!("{}/{}", count.players, count.max_players),
+        Err(_) => "?".to_string(),
+    }
 }
@@ -0,0 +1,234 @@
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// how long cgroup cpu usage is sampled for to compute a percentage
+const CPU_SAMPLE_DURATION: Duration = Duration::from_secs(1);
+
+/// Resource usage of a single server
+#[derive(Debug, Clone, Default)]
+pub struct ResourceUsage {
+    /// CPU usage, where 100% is one full core
+    pub cpu_percent: Option<f64>,
+    pub memory_used: Option<u64>,
+    pub memory_limit: Option<u64>,
+    pub network_rx: Option<u64>,
+    pub network_tx: Option<u64>,
+    pub uptime: Option<Duration>,
+}
+
+impl Display for ResourceUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+
+        if let Some(cpu) = self.cpu_percent {
+            parts.push(format!("CPU: {:.1}%", cpu));
+        }
+
+        match (self.memory_used, self.memory_limit) {
+            (Some(used), Some(limit)) => parts.push(format!(
+                "Memory: {} / {}",
+                format_bytes(used),
+                format_bytes(limit)
+            )),
+            (Some(used), None) => parts.push(format!("Memory: {}", format_bytes(used))),
+            _ => {}
+        }
+
+        if let (Some(rx), Some(tx)) = (self.network_rx, self.network_tx) {
+            parts.push(format!(
+                "Network: {} in / {} out",
+                format_bytes(rx),
+                format_bytes(tx)
+            ));
+        }
+
+        if let Some(uptime) = self.uptime {
+            parts.push(format!("Uptime: {}", format_uptime(uptime)));
+        }
+
+        if parts.is_empty() {
+            write!(f, "No resource usage available")
+        } else {
+            write!(f, "{}", parts.join(" | "))
+        }
+    }
+}
+
+/// Resource usage of the machine the bot runs on
+#[derive(Debug, Clone)]
+pub struct HostUsage {
+    pub memory_total: u64,
+    pub memory_available: u64,
+    pub load_average: [f64; 3],
+    pub uptime: Duration,
+}
+
+impl HostUsage {
+    /// Reads the host usage from `/proc`
+    pub fn read() -> io::Result<Self> {
+        let meminfo = fs::read_to_string("/proc/meminfo")?;
+        let memory_total = meminfo_value(&meminfo, "MemTotal")?;
+        let memory_available = meminfo_value(&meminfo, "MemAvailable")?;
+
+        let loadavg = fs::read_to_string("/proc/loadavg")?;
+        let mut load_average = [0.0; 3];
+        for (load, value) in load_average.iter_mut().zip(loadavg.split_whitespace()) {
+            *load = value.parse().map_err(invalid_data)?;
+        }
+
+        Ok(Self {
+            memory_total,
+            memory_available,
+            load_average,
+            uptime: read_host_uptime()?,
+        })
+    }
+}
+
+impl Display for HostUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Memory: {} / {} available | Load: {:.2} {:.2} {:.2} | Uptime: {}",
+            format_bytes(self.memory_available),
+            format_bytes(self.memory_total),
+            self.load_average[0],
+            self.load_average[1],
+            self.load_average[2],
+            format_uptime(self.uptime)
+        )
+    }
+}
+
+/// Reads resource usage of a systemd unit from its cgroup (v2)
+pub async fn read_cgroup_usage(
+    cgroup: &str,
+    active_since_boot: Duration,
+) -> io::Result<ResourceUsage> {
+    let dir = cgroup_dir(cgroup);
+
+    let cpu_before = read_cpu_usage(&dir)?;
+    tokio::time::sleep(CPU_SAMPLE_DURATION).await;
+    let cpu_after = read_cpu_usage(&dir)?;
+
+    let cpu_percent = (cpu_after.saturating_sub(cpu_before)) as f64
+        / CPU_SAMPLE_DURATION.as_micros() as f64
+        * 100.0;
+
+    let memory_used = read_trimmed(&dir.join("memory.current"))?
+        .parse()
+        .map_err(invalid_data)?;
+
+    // "max" means the unit has no memory limit
+    let memory_limit = read_trimmed(&dir.join("memory.max"))?.parse().ok();
+
+    Ok(ResourceUsage {
+        cpu_percent: Some(cpu_percent),
+        memory_used: Some(memory_used),
+        memory_limit,
+        network_rx: None,
+        network_tx: None,
+        uptime: read_host_uptime()?.checked_sub(active_since_boot),
+    })
+}
+
+fn cgroup_dir(cgroup: &str) -> PathBuf {
+    Path::new("/sys/fs/cgroup").join(cgroup.trim_start_matches('/'))
+}
+
+fn read_cpu_usage(dir: &Path) -> io::Result<u64> {
+    let stat = fs::read_to_string(dir.join("cpu.stat"))?;
+
+    stat.lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))
+        .ok_or_else(|| invalid_data("missing usage_usec in cpu.stat"))?
+        .trim()
+        .parse()
+        .map_err(invalid_data)
+}
+
+fn read_host_uptime() -> io::Result<Duration> {
+    let uptime = fs::read_to_string("/proc/uptime")?;
+    let secs: f64 = uptime
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .parse()
+        .map_err(invalid_data)?;
+
+    Ok(Duration::from_secs_f64(secs))
+}
+
+fn read_trimmed(path: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+fn meminfo_value(meminfo: &str, key: &str) -> io::Result<u64> {
+    let kib: u64 = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .ok_or_else(|| invalid_data(format!("missing {} in /proc/meminfo", key)))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .map_err(invalid_data)?;
+
+    Ok(kib * 1024)
+}
+
+fn invalid_data<E: ToString>(err: E) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err.to_string())
+}
+
+/// Parses sizes as reported by docker, e.g. `1.5GiB` or `300kB`
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(split);
+    let value: f64 = value.parse().ok()?;
+
+    let multiplier = match unit.trim() {
+        "B" | "" => 1.0,
+        "kB" | "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+
+    Some((value * multiplier) as u64)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_uptime(uptime: Duration) -> String {
+    // seconds aren't useful for uptime
+    humantime::format_duration(Duration::from_secs(uptime.as_secs() / 60 * 60)).to_string()
+}
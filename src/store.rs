@@ -0,0 +1,350 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::{
+    model::id::{ChannelId, GuildId, RoleId, UserId},
+    prelude::TypeMapKey,
+};
+
+use tracing::info;
+
+use crate::audit::{AuditActor, AuditEntry};
+
+/// Each migration upgrades the schema by one version, tracked in sqlite's `user_version`.
+/// Released migrations must never change; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: voice channel occupancy, pending voice messages, schedules, and status history
+    "CREATE TABLE voice_members (
+        role_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        PRIMARY KEY (role_id, user_id)
+    );
+    CREATE TABLE pending_voice_messages (
+        role_id INTEGER NOT NULL,
+        is_start INTEGER NOT NULL,
+        due_at INTEGER NOT NULL,
+        PRIMARY KEY (role_id, is_start)
+    );
+    CREATE TABLE schedules (
+        name TEXT PRIMARY KEY,
+        last_run INTEGER NOT NULL
+    );
+    CREATE TABLE status_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        server_name TEXT NOT NULL,
+        status TEXT NOT NULL,
+        time INTEGER NOT NULL
+    );
+    CREATE INDEX status_history_server ON status_history (server_name, time);",
+    // 2: audit log of server management actions
+    "CREATE TABLE audit_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        time INTEGER NOT NULL,
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        server_name TEXT,
+        command TEXT NOT NULL,
+        result TEXT NOT NULL,
+        duration_ms INTEGER NOT NULL
+    );
+    CREATE INDEX audit_log_server ON audit_log (server_name, time);",
+    // 3: audit entries from api clients, which have a client name instead of a user id
+    "ALTER TABLE audit_log ADD COLUMN api_client TEXT;",
+    // 4: the user and channel whose join or leave caused a pending voice message
+    "ALTER TABLE pending_voice_messages ADD COLUMN user_id INTEGER;
+    ALTER TABLE pending_voice_messages ADD COLUMN channel_id INTEGER;",
+];
+
+pub struct StoreKey;
+impl TypeMapKey for StoreKey {
+    type Value = Arc<Store>;
+}
+
+/// A start or end message for a voice channel that was waiting to be sent
+#[derive(Debug, Clone)]
+pub struct PendingVoiceMessage {
+    pub role_id: RoleId,
+    pub is_start: bool,
+    pub due_at: SystemTime,
+    // unknown for messages stored by older versions
+    pub user_id: Option<UserId>,
+    pub channel_id: Option<ChannelId>,
+}
+
+/// Runtime state that has to survive restarts, kept in a sqlite database
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+impl Store {
+    /// Opens the database at the path, creating it and migrating its schema as needed
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut conn)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        // every write is a single statement or transaction, so a panicked holder can't leave
+        // the database half-written
+        self.conn
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Gets the members of every voice channel group, by the role pinged for the group
+    pub fn voice_members(&self) -> rusqlite::Result<HashMap<RoleId, HashSet<UserId>>> {
+        let conn = self.conn();
+        let mut statement = conn.prepare("SELECT role_id, user_id FROM voice_members")?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                RoleId(from_sql_id(row.get(0)?)),
+                UserId(from_sql_id(row.get(1)?)),
+            ))
+        })?;
+
+        let mut members: HashMap<RoleId, HashSet<UserId>> = HashMap::new();
+        for row in rows {
+            let (role_id, user_id) = row?;
+            members.entry(role_id).or_default().insert(user_id);
+        }
+
+        Ok(members)
+    }
+
+    /// Records a user joining or leaving a voice channel group
+    pub fn set_voice_member(
+        &self,
+        role_id: RoleId,
+        user_id: UserId,
+        is_member: bool,
+    ) -> rusqlite::Result<()> {
+        let params = params![to_sql_id(role_id.0), to_sql_id(user_id.0)];
+
+        if is_member {
+            self.conn().execute(
+                "INSERT OR IGNORE INTO voice_members (role_id, user_id) VALUES (?1, ?2)",
+                params,
+            )?;
+        } else {
+            self.conn().execute(
+                "DELETE FROM voice_members WHERE role_id = ?1 AND user_id = ?2",
+                params,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the members of a voice channel group
+    pub fn replace_voice_members(
+        &self,
+        role_id: RoleId,
+        user_ids: &HashSet<UserId>,
+    ) -> rusqlite::Result<()> {
+        let mut conn = self.conn();
+        let transaction = conn.transaction()?;
+
+        transaction.execute(
+            "DELETE FROM voice_members WHERE role_id = ?1",
+            params![to_sql_id(role_id.0)],
+        )?;
+
+        for user_id in user_ids {
+            transaction.execute(
+                "INSERT INTO voice_members (role_id, user_id) VALUES (?1, ?2)",
+                params![to_sql_id(role_id.0), to_sql_id(user_id.0)],
+            )?;
+        }
+
+        transaction.commit()
+    }
+
+    pub fn pending_voice_messages(&self) -> rusqlite::Result<Vec<PendingVoiceMessage>> {
+        let conn = self.conn();
+        let mut statement = conn.prepare(
+            "SELECT role_id, is_start, due_at, user_id, channel_id FROM pending_voice_messages",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(PendingVoiceMessage {
+                role_id: RoleId(from_sql_id(row.get(0)?)),
+                is_start: row.get(1)?,
+                due_at: from_sql_time(row.get(2)?),
+                user_id: row
+                    .get::<_, Option<i64>>(3)?
+                    .map(|id| UserId(from_sql_id(id))),
+                channel_id: row
+                    .get::<_, Option<i64>>(4)?
+                    .map(|id| ChannelId(from_sql_id(id))),
+            })
+        })?;
+
+        rows.collect()
+    }
+
+    pub fn add_pending_voice_message(&self, message: &PendingVoiceMessage) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO pending_voice_messages (role_id, is_start, due_at, user_id, channel_id)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                to_sql_id(message.role_id.0),
+                message.is_start,
+                to_sql_time(message.due_at),
+                message.user_id.map(|user_id| to_sql_id(user_id.0)),
+                message.channel_id.map(|channel_id| to_sql_id(channel_id.0))
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn remove_pending_voice_message(
+        &self,
+        role_id: RoleId,
+        is_start: bool,
+    ) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "DELETE FROM pending_voice_messages WHERE role_id = ?1 AND is_start = ?2",
+            params![to_sql_id(role_id.0), is_start],
+        )?;
+
+        Ok(())
+    }
+
+    /// Gets when the named scheduled task last ran
+    pub fn last_run(&self, name: &str) -> rusqlite::Result<Option<SystemTime>> {
+        self.conn()
+            .query_row(
+                "SELECT last_run FROM schedules WHERE name = ?1",
+                params![name],
+                |row| row.get(0).map(from_sql_time),
+            )
+            .optional()
+    }
+
+    pub fn set_last_run(&self, name: &str, time: SystemTime) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO schedules (name, last_run) VALUES (?1, ?2)",
+            params![name, to_sql_time(time)],
+        )?;
+
+        Ok(())
+    }
+
+    /// Records a server changing status, for statistics
+    pub fn record_status(
+        &self,
+        server_name: &str,
+        status: &str,
+        time: SystemTime,
+    ) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO status_history (server_name, status, time) VALUES (?1, ?2, ?3)",
+            params![server_name, status, to_sql_time(time)],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn record_audit(&self, entry: &AuditEntry) -> rusqlite::Result<()> {
+        // user ids are required, so api clients are recorded as user 0
+        let (user_id, api_client) = match &entry.actor {
+            AuditActor::User(user_id) => (user_id.0, None),
+            AuditActor::Api(name) => (0, Some(name)),
+        };
+
+        self.conn().execute(
+            "INSERT INTO audit_log (time, guild_id, user_id, api_client, server_name, command, result, duration_ms)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                to_sql_time(entry.time),
+                to_sql_id(entry.guild_id.0),
+                to_sql_id(user_id),
+                api_client,
+                entry.server_name,
+                entry.command,
+                entry.result,
+                entry.duration.as_millis() as i64
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Gets the most recent actions on the server, or on any server if not given, newest first.
+    /// Only actions taken in the guild are included, if one is given.
+    pub fn audit_entries(
+        &self,
+        server_name: Option<&str>,
+        guild_id: Option<GuildId>,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<AuditEntry>> {
+        let conn = self.conn();
+        let mut statement = conn.prepare(
+            "SELECT time, guild_id, user_id, api_client, server_name, command, result, duration_ms FROM audit_log
+            WHERE (?1 IS NULL OR server_name = ?1) AND (?2 IS NULL OR guild_id = ?2)
+            ORDER BY time DESC, id DESC LIMIT ?3",
+        )?;
+        let guild_id = guild_id.map(|guild_id| to_sql_id(guild_id.0));
+        let rows = statement.query_map(params![server_name, guild_id, limit], |row| {
+            let actor = match row.get::<_, Option<String>>(3)? {
+                Some(api_client) => AuditActor::Api(api_client),
+                None => AuditActor::User(UserId(from_sql_id(row.get(2)?))),
+            };
+
+            Ok(AuditEntry {
+                time: from_sql_time(row.get(0)?),
+                guild_id: GuildId(from_sql_id(row.get(1)?)),
+                actor,
+                server_name: row.get(4)?,
+                command: row.get(5)?,
+                result: row.get(6)?,
+                duration: Duration::from_millis(row.get::<_, i64>(7)?.max(0) as u64),
+            })
+        })?;
+
+        rows.collect()
+    }
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = conn.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+
+        info!(version = index + 1, "Migrated state store");
+    }
+
+    Ok(())
+}
+
+// discord ids use the full range of a u64, but sqlite integers are signed
+fn to_sql_id(id: u64) -> i64 {
+    id as i64
+}
+
+fn from_sql_id(id: i64) -> u64 {
+    id as u64
+}
+
+fn to_sql_time(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+fn from_sql_time(secs: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)
+}
@@ -0,0 +1,52 @@
+use std::io::{self, IsTerminal};
+
+use tracing::warn;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::config::{LogFormat, LoggingConfig};
+
+const DEFAULT_FILTER: &str = "info";
+
+/// Installs the global tracing subscriber. The `RUST_LOG` environment variable takes
+/// precedence over the configured filter.
+pub fn init(config: &LoggingConfig) {
+    let configured = config.filter.as_deref().unwrap_or(DEFAULT_FILTER);
+    let (filter, invalid_filter) = match EnvFilter::try_from_default_env() {
+        Ok(filter) => (filter, None),
+        Err(_) => match EnvFilter::try_new(configured) {
+            Ok(filter) => (filter, None),
+            Err(err) => (EnvFilter::new(DEFAULT_FILTER), Some(err)),
+        },
+    };
+
+    let registry = tracing_subscriber::registry().with(filter);
+    // colors only make sense on a terminal, not in files or the journal
+    let ansi = io::stdout().is_terminal();
+
+    match config.format {
+        LogFormat::Pretty => registry.with(fmt::layer().pretty().with_ansi(ansi)).init(),
+        LogFormat::Compact => registry.with(fmt::layer().compact().with_ansi(ansi)).init(),
+        LogFormat::Json => registry
+            .with(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true),
+            )
+            .init(),
+        LogFormat::Journald => match tracing_journald::layer() {
+            Ok(layer) => registry.with(layer).init(),
+            Err(err) => {
+                // journald timestamps lines itself
+                registry
+                    .with(fmt::layer().compact().without_time().with_ansi(ansi))
+                    .init();
+                warn!(%err, "Cannot connect to journald; logging to stdout");
+            }
+        },
+    }
+
+    if let Some(err) = invalid_filter {
+        warn!(%err, filter = configured, "Invalid log filter; using {}", DEFAULT_FILTER);
+    }
+}
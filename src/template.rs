@@ -0,0 +1,320 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The variables a kind of template can use
+pub trait Variables {
+    const NAMES: &'static [&'static str];
+    // variables that take an argument, e.g. `{port:27015/udp}`
+    const WITH_ARGUMENT: &'static [&'static str] = &[];
+}
+
+pub struct DockerConnectVars;
+impl Variables for DockerConnectVars {
+    const NAMES: &'static [&'static str] = &["public_ip", "port", "server_name", "players"];
+    const WITH_ARGUMENT: &'static [&'static str] = &["port"];
+}
+
+pub struct ConnectVars;
+impl Variables for ConnectVars {
+    const NAMES: &'static [&'static str] = &["public_ip", "server_name", "players"];
+}
+
+pub struct VoiceVars;
+impl Variables for VoiceVars {
+    const NAMES: &'static [&'static str] = &["user", "channel", "role"];
+}
+
+pub struct NotificationVars;
+impl Variables for NotificationVars {
+    const NAMES: &'static [&'static str] = &[
+        "server_name",
+        "status",
+        "previous_status",
+        "user",
+        "role",
+        "players",
+    ];
+}
+
+// the `$VARIABLE` syntax used before templates, still accepted where the variable exists
+const LEGACY_VARIABLES: &[(&str, &str)] = &[
+    ("$PUBLIC_IP", "public_ip"),
+    ("$SERVER_NAME", "server_name"),
+    ("$PREVIOUS_STATUS", "previous_status"),
+    ("$STATUS", "status"),
+];
+
+/// A string with `{variable}` or `{variable:argument}` placeholders; `{{` and `}}` are literal braces.
+/// Templates are checked against the variables of their kind when the config is loaded.
+#[derive(Serialize, Deserialize)]
+#[serde(
+    try_from = "String",
+    into = "String",
+    bound(deserialize = "V: Variables", serialize = "")
+)]
+pub struct Template<V> {
+    source: String,
+    segments: Vec<Segment>,
+    variables: PhantomData<fn() -> V>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable { name: String, arg: Option<String> },
+}
+
+impl<V: Variables> Template<V> {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut rest = source;
+
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                literal.push(c);
+                rest = &rest[2..];
+            } else if c == '{' {
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| format!("Unclosed '{{' in template {:?}", source))?;
+                let (name, arg) = match rest[1..end].split_once(':') {
+                    Some((name, arg)) => (name.trim(), Some(arg.trim().to_string())),
+                    None => (rest[1..end].trim(), None),
+                };
+
+                Self::check_variable(source, name, arg.is_some())?;
+
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Variable {
+                    name: name.to_string(),
+                    arg,
+                });
+                rest = &rest[end + 1..];
+            } else if c == '}' {
+                return Err(format!(
+                    "Unmatched '}}' in template {:?}; use '}}}}' for a literal brace",
+                    source
+                ));
+            } else if let Some((legacy, name)) = LEGACY_VARIABLES
+                .iter()
+                .find(|(legacy, name)| rest.starts_with(legacy) && V::NAMES.contains(name))
+            {
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Variable {
+                    name: name.to_string(),
+                    arg: None,
+                });
+                rest = &rest[legacy.len()..];
+            } else {
+                literal.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+            variables: PhantomData,
+        })
+    }
+
+    fn check_variable(source: &str, name: &str, has_arg: bool) -> Result<(), String> {
+        if !V::NAMES.contains(&name) {
+            return Err(format!(
+                "Unknown variable {{{}}} in template {:?}; expected one of {}",
+                name,
+                source,
+                V::NAMES
+                    .iter()
+                    .map(|name| format!("{{{}}}", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        if has_arg && !V::WITH_ARGUMENT.contains(&name) {
+            return Err(format!(
+                "Variable {{{}}} in template {:?} doesn't take an argument",
+                name, source
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<V> Template<V> {
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Returns true if the template uses the variable, with any argument
+    pub fn uses(&self, variable: &str) -> bool {
+        self.arguments(variable).next().is_some()
+    }
+
+    /// Gets the argument of each use of the variable
+    pub fn arguments<'a>(&'a self, variable: &'a str) -> impl Iterator<Item = Option<&'a str>> {
+        self.segments
+            .iter()
+            .filter_map(move |segment| match segment {
+                Segment::Variable { name, arg } if name == variable => Some(arg.as_deref()),
+                _ => None,
+            })
+    }
+
+    /// Fills in each variable with the value for its name and argument
+    pub fn render(&self, mut value: impl FnMut(&str, Option<&str>) -> String) -> String {
+        let mut rendered = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => rendered.push_str(literal),
+                Segment::Variable { name, arg } => rendered.push_str(&value(name, arg.as_deref())),
+            }
+        }
+
+        rendered
+    }
+}
+
+impl<V: Variables> TryFrom<String> for Template<V> {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Self::parse(&source)
+    }
+}
+
+impl<V> From<Template<V>> for String {
+    fn from(template: Template<V>) -> Self {
+        template.source
+    }
+}
+
+impl<V> Clone for Template<V> {
+    fn clone(&self) -> Self {
+        Self {
+            source: self.source.clone(),
+            segments: self.segments.clone(),
+            variables: PhantomData,
+        }
+    }
+}
+
+impl<V> Debug for Template<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // renders each variable as its name and argument, e.g. `<port:27015/udp>`
+    fn render<V: Variables>(source: &str) -> String {
+        Template::<V>::parse(source)
+            .unwrap()
+            .render(|name, arg| match arg {
+                Some(arg) => format!("<{}:{}>", name, arg),
+                None => format!("<{}>", name),
+            })
+    }
+
+    #[test]
+    fn fills_in_variables() {
+        assert_eq!(
+            render::<DockerConnectVars>("connect {public_ip}:{ port : 27015/udp } ({players})"),
+            "connect <public_ip>:<port:27015/udp> (<players>)"
+        );
+        assert_eq!(render::<VoiceVars>("{user} joined"), "<user> joined");
+    }
+
+    #[test]
+    fn rejects_unknown_variable() {
+        let err = Template::<VoiceVars>::parse("{user} started {server_name}").unwrap_err();
+
+        assert_eq!(
+            err,
+            "Unknown variable {server_name} in template \"{user} started {server_name}\"; \
+             expected one of {user}, {channel}, {role}"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_variable_at_config_load() {
+        let err = ron::from_str::<Template<ConnectVars>>("\"{public_ip}:{port}\"").unwrap_err();
+
+        assert!(err.to_string().contains("Unknown variable {port}"));
+    }
+
+    #[test]
+    fn rejects_argument_on_variable_without_one() {
+        let err = Template::<DockerConnectVars>::parse("{public_ip:v6}").unwrap_err();
+
+        assert_eq!(
+            err,
+            "Variable {public_ip} in template \"{public_ip:v6}\" doesn't take an argument"
+        );
+    }
+
+    #[test]
+    fn escapes_doubled_braces() {
+        assert_eq!(
+            render::<ConnectVars>("{{\"ip\": \"{public_ip}\"}}"),
+            "{\"ip\": \"<public_ip>\"}"
+        );
+        assert_eq!(render::<ConnectVars>("{{public_ip}}"), "{public_ip}");
+    }
+
+    #[test]
+    fn rejects_unclosed_brace() {
+        assert_eq!(
+            Template::<ConnectVars>::parse("{public_ip").unwrap_err(),
+            "Unclosed '{' in template \"{public_ip\""
+        );
+        assert_eq!(
+            Template::<ConnectVars>::parse("public_ip}").unwrap_err(),
+            "Unmatched '}' in template \"public_ip}\"; use '}}' for a literal brace"
+        );
+    }
+
+    #[test]
+    fn accepts_legacy_variables() {
+        assert_eq!(
+            render::<DockerConnectVars>("$PUBLIC_IP:27015"),
+            "<public_ip>:27015"
+        );
+        assert_eq!(
+            render::<NotificationVars>("$SERVER_NAME went from $PREVIOUS_STATUS to $STATUS"),
+            "<server_name> went from <previous_status> to <status>"
+        );
+    }
+
+    #[test]
+    fn leaves_legacy_variables_of_other_kinds_as_is() {
+        assert_eq!(render::<VoiceVars>("$PUBLIC_IP"), "$PUBLIC_IP");
+    }
+
+    #[test]
+    fn keeps_source_when_serialized() {
+        let source = "$PUBLIC_IP:{port:27015}";
+        let template = Template::<DockerConnectVars>::parse(source).unwrap();
+
+        assert_eq!(String::from(template), source);
+    }
+}
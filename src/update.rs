@@ -0,0 +1,883 @@
+use std::{
+    fmt::{Display, Formatter},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashSet;
+use serde_json::Value;
+use serenity::prelude::{TypeMap, TypeMapKey};
+
+use tracing::warn;
+
+use crate::{
+    command::SlashCommand,
+    server_commands::{run_async_command, ServerError},
+};
+
+// how long a recreated container has to become healthy before it is rolled back
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(180);
+// containers without a healthcheck count as healthy once they have stayed up this long
+const SETTLE_DURATION: Duration = Duration::from_secs(20);
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// label images commonly use to report the version of the software they contain
+const VERSION_LABEL: &str = "org.opencontainers.image.version";
+
+/// Servers with an update in progress, so two updates never recreate the same container at once
+pub struct UpdatesInProgressKey;
+impl TypeMapKey for UpdatesInProgressKey {
+    type Value = Arc<DashSet<String>>;
+}
+
+/// Marks an update of the server as in progress until dropped
+pub struct UpdateGuard {
+    in_progress: Arc<DashSet<String>>,
+    server_name: String,
+}
+
+impl Drop for UpdateGuard {
+    fn drop(&mut self) {
+        self.in_progress.remove(&self.server_name);
+    }
+}
+
+/// Marks an update of the server as in progress, or returns `None` if one already is
+pub fn begin_update(data: &TypeMap, server_name: &str) -> Option<UpdateGuard> {
+    let in_progress = data.get::<UpdatesInProgressKey>().unwrap().clone();
+
+    if !in_progress.insert(server_name.to_string()) {
+        return None;
+    }
+
+    Some(UpdateGuard {
+        in_progress,
+        server_name: server_name.to_string(),
+    })
+}
+
+/// The versions of a server before and after an update
+#[derive(Debug, Clone)]
+pub struct UpdateSummary {
+    pub previous_version: Option<String>,
+    pub current_version: Option<String>,
+    pub updated: bool,
+}
+
+impl Display for UpdateSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.updated, &self.previous_version, &self.current_version) {
+            (true, Some(previous), Some(current)) => {
+                write!(f, "was updated from {} to {}", previous, current)
+            }
+            (true, _, _) => write!(f, "was updated"),
+            (false, _, Some(current)) => write!(f, "is already up to date ({})", current),
+            (false, _, None) => write!(f, "is already up to date"),
+        }
+    }
+}
+
+/// Pulls the image tag the container was created from, and if it changed, recreates the
+/// container from the new image with the same configuration. The old container is kept as
+/// `<name>-previous` until the new one is healthy, and is restored if it never becomes healthy.
+pub async fn update_container(
+    engine: &[String],
+    container_name: &str,
+) -> Result<UpdateSummary, ServerError> {
+    let container = inspect(engine, &["container", "inspect", container_name]).await?;
+    let image = str_field(&container, &["Config", "Image"]).to_string();
+    let previous_image_id = str_field(&container, &["Image"]).to_string();
+    let was_running = container["State"]["Running"].as_bool().unwrap_or_default();
+
+    docker(engine, &["pull", &image]).await?;
+
+    let previous_image = inspect(engine, &["image", "inspect", &previous_image_id]).await?;
+    let current_image = inspect(engine, &["image", "inspect", &image]).await?;
+
+    if str_field(&current_image, &["Id"]) == previous_image_id {
+        return Ok(UpdateSummary {
+            previous_version: Some(describe_image(&previous_image)),
+            current_version: Some(describe_image(&current_image)),
+            updated: false,
+        });
+    }
+
+    let create_args = create_args(container_name, &image, &container, &previous_image)?;
+    let networks = extra_networks(&container);
+    let previous_name = format!("{}-previous", container_name);
+
+    // left behind if the bot exited during an earlier update; the container itself exists,
+    // so this copy is stale
+    let _ = docker(engine, &["rm", "--force", &previous_name]).await;
+
+    if was_running {
+        docker(engine, &["stop", container_name]).await?;
+    }
+    docker(engine, &["rename", container_name, &previous_name]).await?;
+
+    let recreated = async {
+        let args = create_args.iter().map(String::as_str).collect::<Vec<_>>();
+        docker(engine, &args).await?;
+
+        for network in &networks {
+            let mut args = vec!["network", "connect"];
+            for alias in network_aliases(&container, network) {
+                args.extend(["--alias", alias]);
+            }
+            args.extend([network.as_str(), container_name]);
+
+            docker(engine, &args).await?;
+        }
+
+        if was_running {
+            docker(engine, &["start", container_name]).await?;
+            wait_until_healthy(engine, container_name).await?;
+        }
+
+        Ok::<_, ServerError>(())
+    }
+    .await;
+
+    match recreated {
+        Ok(_) => {
+            if let Err(err) = docker(engine, &["rm", &previous_name]).await {
+                warn!(container = %previous_name, %err, "Failed to remove container after update");
+            }
+
+            Ok(UpdateSummary {
+                previous_version: Some(describe_image(&previous_image)),
+                current_version: Some(describe_image(&current_image)),
+                updated: true,
+            })
+        }
+        Err(err) => {
+            let _ = docker(engine, &["rm", "--force", container_name]).await;
+
+            let restored = async {
+                docker(engine, &["rename", &previous_name, container_name]).await?;
+
+                if was_running {
+                    docker(engine, &["start", container_name]).await?;
+                }
+
+                Ok::<_, ServerError>(())
+            }
+            .await;
+
+            let message = match restored {
+                Ok(_) => format!(
+                    "{}\nRolled back to {}",
+                    err.to_string().trim(),
+                    describe_image(&previous_image)
+                ),
+                Err(rollback_err) => format!(
+                    "{}\nRolling back to {} failed too: {}",
+                    err.to_string().trim(),
+                    describe_image(&previous_image),
+                    rollback_err.to_string().trim()
+                ),
+            };
+
+            Err(update_failed(message))
+        }
+    }
+}
+
+/// Waits for the container to report healthy, or to stay up for a while if it has no healthcheck
+async fn wait_until_healthy(engine: &[String], container_name: &str) -> Result<(), ServerError> {
+    let started = Instant::now();
+
+    loop {
+        let container = inspect(engine, &["container", "inspect", container_name]).await?;
+        let state = &container["State"];
+
+        if !state["Running"].as_bool().unwrap_or_default()
+            || state["Restarting"].as_bool().unwrap_or_default()
+        {
+            return Err(update_failed(format!(
+                "The new container exited with code {}",
+                state["ExitCode"]
+            )));
+        }
+
+        match state["Health"]["Status"].as_str() {
+            Some("healthy") => return Ok(()),
+            Some("unhealthy") => {
+                return Err(update_failed("The new container is unhealthy"));
+            }
+            Some(_) => {}
+            None if started.elapsed() >= SETTLE_DURATION => return Ok(()),
+            None => {}
+        }
+
+        if started.elapsed() >= HEALTH_TIMEOUT {
+            return Err(update_failed(format!(
+                "The new container did not become healthy within {}",
+                humantime::format_duration(HEALTH_TIMEOUT)
+            )));
+        }
+
+        tokio::time::sleep(HEALTH_POLL_INTERVAL).await;
+    }
+}
+
+/// Builds the `docker create` arguments that reproduce the container on its (new) image.
+/// Settings inherited from the previous image are left out, so the new image's defaults apply.
+/// Fails for settings that can't be reproduced, before anything about the container changes.
+fn create_args(
+    container_name: &str,
+    image: &str,
+    container: &Value,
+    image_info: &Value,
+) -> Result<Vec<String>, ServerError> {
+    let config = &container["Config"];
+    let host_config = &container["HostConfig"];
+    let image_config = &image_info["Config"];
+
+    let mut args = vec![
+        "create".to_string(),
+        "--name".to_string(),
+        container_name.to_string(),
+    ];
+    let mut push = |flag: &str, value: String| {
+        args.push(flag.to_string());
+        args.push(value);
+    };
+
+    let image_env = strings(&image_config["Env"]);
+    for env in strings(&config["Env"]) {
+        if !image_env.contains(&env) {
+            push("--env", env);
+        }
+    }
+
+    if let Some(labels) = config["Labels"].as_object() {
+        for (key, value) in labels {
+            if image_config["Labels"][key] != *value {
+                push(
+                    "--label",
+                    format!("{}={}", key, value.as_str().unwrap_or_default()),
+                );
+            }
+        }
+    }
+
+    for (flag, field) in [
+        ("--user", "User"),
+        ("--workdir", "WorkingDir"),
+        ("--stop-signal", "StopSignal"),
+    ] {
+        let value = str_field(config, &[field]);
+        if !value.is_empty() && value != str_field(image_config, &[field]) {
+            push(flag, value.to_string());
+        }
+    }
+
+    if let Some(mounts) = container["Mounts"].as_array() {
+        for mount in mounts {
+            let target = str_field(mount, &["Destination"]);
+            let mut spec = match str_field(mount, &["Type"]) {
+                "bind" => format!(
+                    "type=bind,source={},target={}",
+                    str_field(mount, &["Source"]),
+                    target
+                ),
+                // anonymous volumes are mounted by name so their data carries over
+                "volume" => format!(
+                    "type=volume,source={},target={}",
+                    str_field(mount, &["Name"]),
+                    target
+                ),
+                "tmpfs" => format!("type=tmpfs,target={}", target),
+                other => {
+                    return Err(update_failed(format!(
+                        "Can't recreate the container with its {} mount at {}",
+                        other, target
+                    )))
+                }
+            };
+
+            if mount["RW"].as_bool() == Some(false) {
+                spec.push_str(",readonly");
+            }
+            push("--mount", spec);
+        }
+    }
+
+    if !strings(&host_config["VolumesFrom"]).is_empty() {
+        // the volumes show up among the mounts too, so they can't be told apart to recreate both
+        return Err(update_failed(
+            "Can't recreate a container that mounts the volumes of other containers",
+        ));
+    }
+
+    if let Some(ports) = host_config["PortBindings"].as_object() {
+        for (container_port, bindings) in ports {
+            for binding in bindings.as_array().into_iter().flatten() {
+                let host_ip = str_field(binding, &["HostIp"]);
+                let host_port = str_field(binding, &["HostPort"]);
+
+                let publish = match (host_ip, host_port) {
+                    ("", "") => container_port.to_string(),
+                    ("", port) => format!("{}:{}", port, container_port),
+                    (ip, port) if ip.contains(':') => {
+                        format!("[{}]:{}:{}", ip, port, container_port)
+                    }
+                    (ip, port) => format!("{}:{}:{}", ip, port, container_port),
+                };
+                push("--publish", publish);
+            }
+        }
+    }
+
+    // published ports are exposed anyway
+    if let Some(exposed) = config["ExposedPorts"].as_object() {
+        for port in exposed.keys() {
+            if image_config["ExposedPorts"].get(port).is_none()
+                && host_config["PortBindings"].get(port).is_none()
+            {
+                push("--expose", port.to_string());
+            }
+        }
+    }
+
+    let network_mode = str_field(host_config, &["NetworkMode"]);
+    if !network_mode.is_empty() && network_mode != "default" {
+        push("--network", network_mode.to_string());
+
+        for alias in network_aliases(container, network_mode) {
+            push("--network-alias", alias.to_string());
+        }
+    }
+
+    // docker names containers' hosts after their short id, and containers sharing another's
+    // network take its host name
+    let id = str_field(container, &["Id"]);
+    let shares_network = network_mode == "host" || network_mode.starts_with("container:");
+    let hostname = str_field(config, &["Hostname"]);
+    if !shares_network && !hostname.is_empty() && hostname != &id[..id.len().min(12)] {
+        push("--hostname", hostname.to_string());
+    }
+    let domainname = str_field(config, &["Domainname"]);
+    if !shares_network && !domainname.is_empty() {
+        push("--domainname", domainname.to_string());
+    }
+
+    for (flag, field) in [("--ipc", "IpcMode"), ("--pid", "PidMode")] {
+        let mode = str_field(host_config, &[field]);
+        if !mode.is_empty() {
+            push(flag, mode.to_string());
+        }
+    }
+
+    let restart = &host_config["RestartPolicy"];
+    match str_field(restart, &["Name"]) {
+        "" | "no" => {}
+        "on-failure" => push(
+            "--restart",
+            format!(
+                "on-failure:{}",
+                restart["MaximumRetryCount"].as_u64().unwrap_or_default()
+            ),
+        ),
+        policy => push("--restart", policy.to_string()),
+    }
+
+    if let Some(memory) = host_config["Memory"].as_u64().filter(|memory| *memory > 0) {
+        push("--memory", memory.to_string());
+    }
+    if let Some(cpus) = host_config["NanoCpus"].as_u64().filter(|cpus| *cpus > 0) {
+        push("--cpus", (cpus as f64 / 1e9).to_string());
+    }
+    if let Some(size) = host_config["ShmSize"].as_u64().filter(|size| *size > 0) {
+        push("--shm-size", size.to_string());
+    }
+
+    for (flag, field) in [
+        ("--cap-add", "CapAdd"),
+        ("--cap-drop", "CapDrop"),
+        ("--add-host", "ExtraHosts"),
+        ("--dns", "Dns"),
+        ("--security-opt", "SecurityOpt"),
+    ] {
+        for value in strings(&host_config[field]) {
+            push(flag, value);
+        }
+    }
+
+    for device in host_config["Devices"].as_array().into_iter().flatten() {
+        push(
+            "--device",
+            format!(
+                "{}:{}:{}",
+                str_field(device, &["PathOnHost"]),
+                str_field(device, &["PathInContainer"]),
+                str_field(device, &["CgroupPermissions"])
+            ),
+        );
+    }
+
+    for request in host_config["DeviceRequests"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        push("--gpus", gpus(request)?);
+    }
+
+    for ulimit in host_config["Ulimits"].as_array().into_iter().flatten() {
+        push(
+            "--ulimit",
+            format!(
+                "{}={}:{}",
+                str_field(ulimit, &["Name"]),
+                ulimit["Soft"],
+                ulimit["Hard"]
+            ),
+        );
+    }
+
+    if let Some(sysctls) = host_config["Sysctls"].as_object() {
+        for (key, value) in sysctls {
+            push(
+                "--sysctl",
+                format!("{}={}", key, value.as_str().unwrap_or_default()),
+            );
+        }
+    }
+
+    let log_driver = str_field(&host_config["LogConfig"], &["Type"]);
+    if !log_driver.is_empty() {
+        push("--log-driver", log_driver.to_string());
+
+        if let Some(options) = host_config["LogConfig"]["Config"].as_object() {
+            for (key, value) in options {
+                push(
+                    "--log-opt",
+                    format!("{}={}", key, value.as_str().unwrap_or_default()),
+                );
+            }
+        }
+    }
+
+    for (flag, value) in [
+        ("--privileged", &host_config["Privileged"]),
+        ("--init", &host_config["Init"]),
+        ("--tty", &config["Tty"]),
+        ("--interactive", &config["OpenStdin"]),
+    ] {
+        if value.as_bool() == Some(true) {
+            args.push(flag.to_string());
+        }
+    }
+
+    // the entrypoint flag only takes the executable; the rest of it goes before the command
+    let entrypoint = strings(&config["Entrypoint"]);
+    let entrypoint_changed = entrypoint != strings(&image_config["Entrypoint"]);
+    if entrypoint_changed && !entrypoint.is_empty() {
+        args.push("--entrypoint".to_string());
+        args.push(entrypoint[0].clone());
+    }
+
+    args.push(image.to_string());
+
+    let cmd = strings(&config["Cmd"]);
+    if entrypoint_changed {
+        args.extend(entrypoint.into_iter().skip(1));
+        args.extend(cmd);
+    } else if cmd != strings(&image_config["Cmd"]) {
+        args.extend(cmd);
+    }
+
+    Ok(args)
+}
+
+/// The `--gpus` value asking for the same devices as the device request. Only requests
+/// `--gpus` can make are supported, which are the ones for a single set of capabilities.
+fn gpus(request: &Value) -> Result<String, ServerError> {
+    let unsupported = || update_failed("Can't recreate the container with its device requests");
+
+    let capabilities = match request["Capabilities"].as_array().map(Vec::as_slice) {
+        Some([capabilities]) => strings(capabilities),
+        _ => return Err(unsupported()),
+    };
+    if request["Options"]
+        .as_object()
+        .is_some_and(|options| !options.is_empty())
+    {
+        return Err(unsupported());
+    }
+
+    // a csv list, so fields that are lists themselves are quoted
+    let mut fields = vec![];
+    let driver = str_field(request, &["Driver"]);
+    if !driver.is_empty() {
+        fields.push(format!("driver={}", driver));
+    }
+
+    let device_ids = strings(&request["DeviceIDs"]);
+    match request["Count"].as_i64().unwrap_or_default() {
+        -1 => fields.push("count=all".to_string()),
+        0 if !device_ids.is_empty() => {
+            fields.push(format!("\"device={}\"", device_ids.join(",")));
+        }
+        count if count > 0 && device_ids.is_empty() => fields.push(format!("count={}", count)),
+        _ => return Err(unsupported()),
+    }
+
+    if capabilities != ["gpu"] {
+        fields.push(format!("\"capabilities={}\"", capabilities.join(",")));
+    }
+
+    Ok(fields.join(","))
+}
+
+/// Networks the container is attached to besides its network mode, which can only be
+/// connected after the container is created
+fn extra_networks(container: &Value) -> Vec<String> {
+    let network_mode = str_field(&container["HostConfig"], &["NetworkMode"]);
+
+    container["NetworkSettings"]["Networks"]
+        .as_object()
+        .map(|networks| {
+            networks
+                .keys()
+                .filter(|network| *network != network_mode)
+                .filter(|network| !(network_mode == "default" && *network == "bridge"))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Aliases the container has on the network, other than its short id which docker adds itself
+fn network_aliases<'a>(container: &'a Value, network: &str) -> Vec<&'a str> {
+    let id = str_field(container, &["Id"]);
+    let short_id = &id[..id.len().min(12)];
+
+    container["NetworkSettings"]["Networks"][network]["Aliases"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .filter(|alias| *alias != short_id)
+        .collect()
+}
+
+// e.g. "1.4.2 (sha256:1a2b3c4d5e6f)", or just the short id for images without a version label
+fn describe_image(image: &Value) -> String {
+    let id = str_field(image, &["Id"]);
+    let short_id = &id[..id.len().min("sha256:".len() + 12)];
+
+    match image["Config"]["Labels"][VERSION_LABEL].as_str() {
+        Some(version) => format!("{} ({})", version, short_id),
+        None => short_id.to_string(),
+    }
+}
+
+async fn inspect(engine: &[String], args: &[&str]) -> Result<Value, ServerError> {
+    let output = docker(engine, args).await?;
+    let mut values: Vec<Value> = serde_json::from_str(&output).map_err(update_failed)?;
+
+    if values.is_empty() {
+        return Err(update_failed(format!(
+            "docker {} returned nothing",
+            args.join(" ")
+        )));
+    }
+
+    Ok(values.swap_remove(0))
+}
+
+// engine holds the global arguments pointing the cli at a remote engine, if any
+async fn docker(engine: &[String], args: &[&str]) -> Result<String, ServerError> {
+    let args = engine
+        .iter()
+        .map(String::as_str)
+        .chain(args.iter().copied())
+        .collect::<Vec<_>>();
+
+    run_async_command(SlashCommand::Update, "docker", &args).await
+}
+
+fn str_field<'a>(value: &'a Value, path: &[&str]) -> &'a str {
+    path.iter()
+        .fold(value, |value, key| &value[key])
+        .as_str()
+        .unwrap_or_default()
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn update_failed<E: ToString>(err: E) -> ServerError {
+    ServerError::CommandFailed(SlashCommand::Update, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // recorded from `docker container inspect` and `docker image inspect`, trimmed to the
+    // fields the update reads
+    const CONTAINER: &str = r#"{
+        "Id": "3f4e5d6c7b8a9f0e1d2c3b4a5f6e7d8c9b0a1f2e3d4c5b6a7f8e9d0c1b2a3f4e",
+        "Image": "sha256:0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f9",
+        "Config": {
+            "Hostname": "3f4e5d6c7b8a",
+            "Domainname": "",
+            "User": "",
+            "ExposedPorts": {"27015/tcp": {}, "27015/udp": {}, "27020/udp": {}},
+            "Tty": false,
+            "OpenStdin": true,
+            "Env": [
+                "SERVER_NAME=Raid Night",
+                "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+                "STEAMAPPID=4020"
+            ],
+            "Cmd": null,
+            "Image": "cm2network/gmod:latest",
+            "WorkingDir": "/home/steam",
+            "Entrypoint": ["bash", "entry.sh"],
+            "Labels": {"maintainer": "cm2network", "com.example.group": "games"}
+        },
+        "HostConfig": {
+            "NetworkMode": "games",
+            "PortBindings": {
+                "27015/tcp": [{"HostIp": "", "HostPort": "27015"}],
+                "27015/udp": [{"HostIp": "0.0.0.0", "HostPort": "27015"}]
+            },
+            "RestartPolicy": {"Name": "unless-stopped", "MaximumRetryCount": 0},
+            "VolumesFrom": null,
+            "CapAdd": null,
+            "CapDrop": null,
+            "Dns": [],
+            "ExtraHosts": null,
+            "IpcMode": "private",
+            "PidMode": "",
+            "Privileged": false,
+            "SecurityOpt": null,
+            "ShmSize": 67108864,
+            "Memory": 4294967296,
+            "NanoCpus": 0,
+            "Devices": [],
+            "DeviceRequests": null,
+            "Ulimits": null,
+            "LogConfig": {"Type": "json-file", "Config": {}}
+        },
+        "Mounts": [
+            {
+                "Type": "volume",
+                "Name": "gmod-data",
+                "Source": "/var/lib/docker/volumes/gmod-data/_data",
+                "Destination": "/home/steam/gmod",
+                "RW": true
+            }
+        ],
+        "NetworkSettings": {
+            "Networks": {
+                "games": {"Aliases": ["gmod", "3f4e5d6c7b8a"]}
+            }
+        }
+    }"#;
+
+    const IMAGE: &str = r#"{
+        "Id": "sha256:0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f9",
+        "Config": {
+            "User": "",
+            "ExposedPorts": {"27015/tcp": {}, "27015/udp": {}},
+            "Env": [
+                "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+                "STEAMAPPID=4020"
+            ],
+            "Cmd": null,
+            "WorkingDir": "/home/steam",
+            "Entrypoint": ["bash", "entry.sh"],
+            "Labels": {"maintainer": "cm2network"}
+        }
+    }"#;
+
+    fn container(host_config: Value) -> Value {
+        let mut container: Value = serde_json::from_str(CONTAINER).unwrap();
+        for (key, value) in host_config.as_object().unwrap() {
+            container["HostConfig"][key] = value.clone();
+        }
+
+        container
+    }
+
+    fn args(container: &Value) -> Result<Vec<String>, ServerError> {
+        let image = serde_json::from_str(IMAGE).unwrap();
+        create_args("gmod", "cm2network/gmod:latest", container, &image)
+    }
+
+    // whether the flag is passed with the value
+    fn has(args: &[String], flag: &str, value: &str) -> bool {
+        args.windows(2)
+            .any(|pair| pair[0] == flag && pair[1] == value)
+    }
+
+    #[test]
+    fn recreates_container_with_its_settings() {
+        let args = args(&container(serde_json::json!({}))).unwrap();
+
+        assert_eq!(&args[..3], ["create", "--name", "gmod"]);
+        assert!(has(&args, "--env", "SERVER_NAME=Raid Night"));
+        assert!(has(&args, "--label", "com.example.group=games"));
+        assert!(has(
+            &args,
+            "--mount",
+            "type=volume,source=gmod-data,target=/home/steam/gmod"
+        ));
+        assert!(has(&args, "--publish", "27015:27015/tcp"));
+        assert!(has(&args, "--publish", "0.0.0.0:27015:27015/udp"));
+        assert!(has(&args, "--expose", "27020/udp"));
+        assert!(has(&args, "--network", "games"));
+        assert!(has(&args, "--network-alias", "gmod"));
+        assert!(has(&args, "--restart", "unless-stopped"));
+        assert!(has(&args, "--memory", "4294967296"));
+        assert!(has(&args, "--shm-size", "67108864"));
+        assert!(has(&args, "--ipc", "private"));
+        assert!(args.contains(&"--interactive".to_string()));
+        assert_eq!(args.last().unwrap(), "cm2network/gmod:latest");
+    }
+
+    #[test]
+    fn leaves_out_settings_from_the_image() {
+        let args = args(&container(serde_json::json!({}))).unwrap();
+
+        assert!(!args.iter().any(|arg| arg.starts_with("PATH=")));
+        assert!(!args.iter().any(|arg| arg.starts_with("maintainer=")));
+        assert!(!has(&args, "--expose", "27015/tcp"));
+        assert!(!args.contains(&"--entrypoint".to_string()));
+        assert!(!args.contains(&"--workdir".to_string()));
+        assert!(!args.contains(&"--hostname".to_string()));
+        assert!(!has(&args, "--network-alias", "3f4e5d6c7b8a"));
+    }
+
+    #[test]
+    fn carries_over_devices_and_kernel_settings() {
+        let args = args(&container(serde_json::json!({
+            "Devices": [
+                {
+                    "PathOnHost": "/dev/dri",
+                    "PathInContainer": "/dev/dri",
+                    "CgroupPermissions": "rwm"
+                }
+            ],
+            "Ulimits": [{"Name": "nofile", "Soft": 1024, "Hard": 524288}],
+            "SecurityOpt": ["seccomp=unconfined"],
+            "Sysctls": {"net.ipv4.ip_unprivileged_port_start": "0"},
+            "PidMode": "host",
+        })))
+        .unwrap();
+
+        assert!(has(&args, "--device", "/dev/dri:/dev/dri:rwm"));
+        assert!(has(&args, "--ulimit", "nofile=1024:524288"));
+        assert!(has(&args, "--security-opt", "seccomp=unconfined"));
+        assert!(has(
+            &args,
+            "--sysctl",
+            "net.ipv4.ip_unprivileged_port_start=0"
+        ));
+        assert!(has(&args, "--pid", "host"));
+    }
+
+    #[test]
+    fn carries_over_hostname_set_for_the_container() {
+        let mut container = container(serde_json::json!({}));
+        container["Config"]["Hostname"] = "raid-night".into();
+
+        assert!(has(&args(&container).unwrap(), "--hostname", "raid-night"));
+    }
+
+    #[test]
+    fn carries_over_gpus() {
+        let all = container(serde_json::json!({
+            "DeviceRequests": [
+                {
+                    "Driver": "",
+                    "Count": -1,
+                    "DeviceIDs": null,
+                    "Capabilities": [["gpu"]],
+                    "Options": {}
+                }
+            ],
+        }));
+        let some = container(serde_json::json!({
+            "DeviceRequests": [
+                {
+                    "Driver": "nvidia",
+                    "Count": 0,
+                    "DeviceIDs": ["0", "1"],
+                    "Capabilities": [["gpu", "utility"]],
+                    "Options": {}
+                }
+            ],
+        }));
+
+        assert!(has(&args(&all).unwrap(), "--gpus", "count=all"));
+        assert!(has(
+            &args(&some).unwrap(),
+            "--gpus",
+            r#"driver=nvidia,"device=0,1","capabilities=gpu,utility""#
+        ));
+    }
+
+    #[test]
+    fn refuses_device_requests_gpus_cannot_make() {
+        let container = container(serde_json::json!({
+            "DeviceRequests": [
+                {
+                    "Driver": "cdi",
+                    "Count": 0,
+                    "DeviceIDs": ["vendor.com/device=0"],
+                    "Capabilities": [["gpu"], ["tpu"]],
+                    "Options": {}
+                }
+            ],
+        }));
+
+        assert!(args(&container).is_err());
+    }
+
+    #[test]
+    fn refuses_volumes_from_other_containers() {
+        let container = container(serde_json::json!({ "VolumesFrom": ["gmod-data-container"] }));
+
+        assert!(args(&container).is_err());
+    }
+
+    #[test]
+    fn refuses_unknown_mount_types() {
+        let mut container = container(serde_json::json!({}));
+        container["Mounts"][0]["Type"] = "npipe".into();
+
+        assert!(args(&container).is_err());
+    }
+
+    #[test]
+    fn splits_changed_entrypoint_around_the_image() {
+        let mut container = container(serde_json::json!({}));
+        container["Config"]["Entrypoint"] = serde_json::json!(["bash", "custom.sh"]);
+        container["Config"]["Cmd"] = serde_json::json!(["-maxplayers", "16"]);
+        let args = args(&container).unwrap();
+
+        assert!(has(&args, "--entrypoint", "bash"));
+        assert!(args.ends_with(&[
+            "cm2network/gmod:latest".to_string(),
+            "custom.sh".to_string(),
+            "-maxplayers".to_string(),
+            "16".to_string(),
+        ]));
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, Query, State},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    cache::Cache,
+    http::Http,
+    model::id::GuildId,
+    prelude::{RwLock, TypeMap},
+};
+use tracing::{error, info, instrument};
+
+use crate::{
+    audit::{self, AuditActor, AuditEntry},
+    command::SlashCommand,
+    config::{ApiTokenConfig, WebConfig},
+    dashboard,
+    join::JoinRedirectsKey,
+    metrics, notifications,
+    server_commands::ServerCommands,
+    webhooks::WebhooksKey,
+    ServerKey,
+};
+
+struct ApiState {
+    data: Arc<RwLock<TypeMap>>,
+    http: Arc<Http>,
+    cache: Arc<Cache>,
+    tokens: Vec<ApiTokenConfig>,
+}
+
+/// Serves the bot's http endpoints until the server fails
+pub async fn serve(
+    config: WebConfig,
+    data: Arc<RwLock<TypeMap>>,
+    http: Arc<Http>,
+    cache: Arc<Cache>,
+) {
+    let api = !config.api_tokens.is_empty();
+    let mut app = Router::new();
+
+    if config.metrics {
+        app = app.route("/metrics", get(get_metrics));
+    }
+
+    app = app.route("/join/:name", get(join_server));
+
+    if api {
+        app = app
+            .route("/api/servers", get(list_servers))
+            .route("/api/servers/:name", get(server_status))
+            .route("/api/servers/:name/connect", get(connect_server))
+            .route("/api/servers/:name/:action", post(manage_server))
+            .route("/api/actions", get(recent_actions))
+            .route("/api/webhooks/test", post(test_webhooks));
+    }
+
+    let mut app = app.with_state(Arc::new(ApiState {
+        data: data.clone(),
+        http,
+        cache: cache.clone(),
+        tokens: config.api_tokens,
+    }));
+
+    if config.dashboard {
+        app = app.merge(dashboard::router(data, cache, api));
+    }
+
+    let server = match axum::Server::try_bind(&config.listen) {
+        Ok(server) => server,
+        Err(err) => {
+            error!(listen = %config.listen, %err, "Cannot bind http server");
+            return;
+        }
+    };
+
+    info!(listen = %config.listen, "Serving http");
+
+    if let Err(err) = server.serve(app.into_make_service()).await {
+        error!(%err, "Http server failed");
+    }
+}
+
+async fn get_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        metrics::render(),
+    )
+}
+
+/// An api client, authenticated by its bearer token
+struct ApiClient {
+    name: String,
+    guild_ids: Vec<GuildId>,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ApiState>> for ApiClient {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ApiState>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|token| !token.is_empty());
+
+        let client = token.and_then(|token| {
+            state
+                .tokens
+                .iter()
+                .find(|client| tokens_match(&client.token, token))
+        });
+
+        match client {
+            Some(client) => {
+                let mut guild_ids = client.guild_ids.iter().copied().collect::<Vec<_>>();
+                guild_ids.sort();
+
+                Ok(Self {
+                    name: client.name.clone(),
+                    guild_ids,
+                })
+            }
+            None => Err(ApiReply::error(
+                StatusCode::UNAUTHORIZED,
+                "Invalid api token",
+            )),
+        }
+    }
+}
+
+// compares every byte so the time taken doesn't reveal how much of the token matched
+fn tokens_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[derive(Serialize)]
+struct ApiReply {
+    ok: bool,
+    message: String,
+}
+
+impl ApiReply {
+    fn error(status: StatusCode, message: impl ToString) -> Response {
+        let reply = Self {
+            ok: false,
+            message: message.to_string(),
+        };
+
+        (status, Json(reply)).into_response()
+    }
+}
+
+#[derive(Serialize)]
+struct ServerSummary {
+    name: String,
+    status: String,
+}
+
+async fn list_servers(State(state): State<Arc<ApiState>>, client: ApiClient) -> Response {
+    let servers = state.data.read().await.get::<ServerKey>().unwrap().clone();
+
+    let mut summaries = servers
+        .iter()
+        .filter(|(_, server)| {
+            client
+                .guild_ids
+                .iter()
+                .any(|guild_id| server.get_guild_ids().contains(guild_id))
+        })
+        .map(|(name, server)| ServerSummary {
+            name: name.clone(),
+            status: match server.get_status() {
+                Ok(status) => status.to_string(),
+                Err(err) => format!("Unknown ({})", err),
+            },
+        })
+        .collect::<Vec<_>>();
+    summaries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Json(summaries).into_response()
+}
+
+async fn server_status(
+    State(state): State<Arc<ApiState>>,
+    client: ApiClient,
+    Path(name): Path<String>,
+) -> Response {
+    run_command(&state, &client, SlashCommand::Status, &name).await
+}
+
+async fn connect_server(
+    State(state): State<Arc<ApiState>>,
+    client: ApiClient,
+    Path(name): Path<String>,
+) -> Response {
+    run_command(&state, &client, SlashCommand::Connect, &name).await
+}
+
+async fn manage_server(
+    State(state): State<Arc<ApiState>>,
+    client: ApiClient,
+    Path((name, action)): Path<(String, String)>,
+) -> Response {
+    let command = match action.as_str() {
+        "start" => SlashCommand::Start,
+        "stop" => SlashCommand::Stop,
+        "restart" => SlashCommand::Restart,
+        "pause" => SlashCommand::Pause,
+        "unpause" => SlashCommand::Unpause,
+        "resume" => SlashCommand::Resume,
+        _ => return ApiReply::error(StatusCode::NOT_FOUND, format!("Unknown action {}", action)),
+    };
+
+    run_command(&state, &client, command, &name).await
+}
+
+/// Recent actions in the client's guilds, including the results the public dashboard leaves out
+async fn recent_actions(State(state): State<Arc<ApiState>>, client: ApiClient) -> Response {
+    match dashboard::recent_actions(&state.data, &state.cache, &client.guild_ids).await {
+        Ok(actions) => Json(actions).into_response(),
+        Err(err) => {
+            error!(%err, "Failed to read recent actions");
+            ApiReply::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read recent actions",
+            )
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JoinQuery {
+    expires: u64,
+    signature: String,
+}
+
+/// Redirects a signed join button from /connect-server to the server's join link
+async fn join_server(
+    State(state): State<Arc<ApiState>>,
+    Path(name): Path<String>,
+    Query(query): Query<JoinQuery>,
+) -> Response {
+    let (redirects, servers) = {
+        let data = state.data.read().await;
+
+        (
+            data.get::<JoinRedirectsKey>().unwrap().clone(),
+            data.get::<ServerKey>().unwrap().clone(),
+        )
+    };
+
+    let valid =
+        redirects.is_some_and(|redirects| redirects.verify(&name, query.expires, &query.signature));
+    let server = match servers.get(&name) {
+        Some(server) if valid => server,
+        _ => {
+            return ApiReply::error(
+                StatusCode::NOT_FOUND,
+                "This join link has expired; use /connect-server again",
+            )
+        }
+    };
+
+    match server.join_link(&name).await {
+        Ok(Some(link)) => Redirect::temporary(&link).into_response(),
+        Ok(None) => ApiReply::error(
+            StatusCode::NOT_FOUND,
+            format!("Server {} has no join link", name),
+        ),
+        Err(err) => ApiReply::error(StatusCode::CONFLICT, err),
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookTestResult {
+    url: String,
+    ok: bool,
+    message: Option<String>,
+}
+
+async fn test_webhooks(State(state): State<Arc<ApiState>>, client: ApiClient) -> Response {
+    info!(client = %client.name, "Testing webhooks");
+
+    let webhooks = state
+        .data
+        .read()
+        .await
+        .get::<WebhooksKey>()
+        .unwrap()
+        .clone();
+
+    let results = webhooks
+        .test()
+        .await
+        .into_iter()
+        .map(|(url, result)| WebhookTestResult {
+            url,
+            ok: result.is_ok(),
+            message: result.err(),
+        })
+        .collect::<Vec<_>>();
+
+    Json(results).into_response()
+}
+
+/// Runs a command like the slash command would, as a member of a guild the client and server share
+#[instrument(skip_all, fields(client = %client.name, %command, server = %server_name))]
+async fn run_command(
+    state: &ApiState,
+    client: &ApiClient,
+    command: SlashCommand,
+    server_name: &str,
+) -> Response {
+    info!("Received api command");
+
+    let servers = state.data.read().await.get::<ServerKey>().unwrap().clone();
+    let guild_id = servers.get(server_name).and_then(|server| {
+        let server_guild_ids = server.get_guild_ids();
+
+        client
+            .guild_ids
+            .iter()
+            .find(|guild_id| server_guild_ids.contains(guild_id))
+            .copied()
+    });
+
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        None => {
+            return ApiReply::error(
+                StatusCode::NOT_FOUND,
+                format!("Server {server_name} not found"),
+            )
+        }
+    };
+
+    let started = Instant::now();
+    let reply = command
+        .run_for_server(&state.data, guild_id, server_name, &[])
+        .await;
+
+    metrics::record_command(command, reply.failed);
+
+    audit::record(
+        &state.data,
+        &state.http,
+        AuditEntry {
+            time: SystemTime::now(),
+            guild_id,
+            actor: AuditActor::Api(client.name.clone()),
+            server_name: Some(server_name.to_string()),
+            command: command.to_string(),
+            result: reply.content.clone(),
+            duration: started.elapsed(),
+        },
+    )
+    .await;
+
+    if command.changes_status() {
+        notifications::check_server_status(
+            &state.data,
+            &state.http,
+            server_name,
+            Some(AuditActor::Api(client.name.clone())),
+        )
+        .await;
+    }
+
+    if reply.failed {
+        return ApiReply::error(StatusCode::CONFLICT, reply.content);
+    }
+
+    Json(ApiReply {
+        ok: true,
+        message: reply.content,
+    })
+    .into_response()
+}
@@ -0,0 +1,457 @@
+use std::{
+    fmt::{Display, Formatter},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::Serialize;
+use serenity::{
+    model::id::{ChannelId, RoleId},
+    prelude::{TypeMap, TypeMapKey},
+};
+use sha2::Sha256;
+use tracing::{debug, error, warn};
+
+use crate::{
+    audit::AuditActor,
+    config::{WebhookConfig, WebhookEventKind},
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// deliveries are retried with exponential backoff, for about a minute in total
+const MAX_ATTEMPTS: u32 = 6;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+pub struct WebhooksKey;
+impl TypeMapKey for WebhooksKey {
+    type Value = Arc<Webhooks>;
+}
+
+/// Configured webhook endpoints, and the client to deliver events with
+pub struct Webhooks {
+    client: reqwest::Client,
+    hooks: Vec<WebhookConfig>,
+}
+
+/// An event sent to webhooks, as the json body of a POST request
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    StatusChanged {
+        server: String,
+        previous_status: String,
+        status: String,
+        // who caused the change, if it came from the bot
+        requested_by: Option<WebhookActor>,
+    },
+    Crashed {
+        server: String,
+        previous_status: String,
+    },
+    VoiceSessionStarted {
+        role_id: String,
+        voice_channel_ids: Vec<String>,
+        members: usize,
+    },
+    VoiceSessionEnded {
+        role_id: String,
+        voice_channel_ids: Vec<String>,
+    },
+    // sent on request to check an endpoint, regardless of its events
+    Test,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebhookActor {
+    // ids are strings, since they don't fit in a json number
+    User { id: String },
+    Api { name: String },
+}
+
+impl From<&AuditActor> for WebhookActor {
+    fn from(actor: &AuditActor) -> Self {
+        match actor {
+            AuditActor::User(user_id) => WebhookActor::User {
+                id: user_id.0.to_string(),
+            },
+            AuditActor::Api(name) => WebhookActor::Api { name: name.clone() },
+        }
+    }
+}
+
+impl WebhookEvent {
+    pub fn voice_session(
+        is_start: bool,
+        role_id: RoleId,
+        voice_channel_ids: &[ChannelId],
+        members: usize,
+    ) -> Self {
+        let role_id = role_id.0.to_string();
+        let voice_channel_ids = voice_channel_ids
+            .iter()
+            .map(|channel_id| channel_id.0.to_string())
+            .collect();
+
+        if is_start {
+            WebhookEvent::VoiceSessionStarted {
+                role_id,
+                voice_channel_ids,
+                members,
+            }
+        } else {
+            WebhookEvent::VoiceSessionEnded {
+                role_id,
+                voice_channel_ids,
+            }
+        }
+    }
+
+    fn kind(&self) -> Option<WebhookEventKind> {
+        match self {
+            WebhookEvent::StatusChanged { .. } => Some(WebhookEventKind::StatusChanged),
+            WebhookEvent::Crashed { .. } => Some(WebhookEventKind::Crashed),
+            WebhookEvent::VoiceSessionStarted { .. } => Some(WebhookEventKind::VoiceSessionStarted),
+            WebhookEvent::VoiceSessionEnded { .. } => Some(WebhookEventKind::VoiceSessionEnded),
+            WebhookEvent::Test => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::StatusChanged { .. } => "status_changed",
+            WebhookEvent::Crashed { .. } => "crashed",
+            WebhookEvent::VoiceSessionStarted { .. } => "voice_session_started",
+            WebhookEvent::VoiceSessionEnded { .. } => "voice_session_ended",
+            WebhookEvent::Test => "test",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    // unix timestamp of when the event happened
+    time: u64,
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+}
+
+/// A prepared request, sent the same way on every attempt so receivers can deduplicate it
+#[derive(Clone)]
+struct Delivery {
+    id: String,
+    event: &'static str,
+    body: Arc<Vec<u8>>,
+}
+
+#[derive(Debug)]
+struct DeliveryError {
+    message: String,
+    // network errors, rate limits and server errors may succeed on a later attempt
+    retryable: bool,
+}
+
+impl Display for DeliveryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Webhooks {
+    pub fn new(hooks: Vec<WebhookConfig>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Error creating webhook client");
+
+        Self { client, hooks }
+    }
+
+    /// Sends a test event to every endpoint once, returning the result for each url
+    pub async fn test(&self) -> Vec<(String, Result<(), String>)> {
+        let delivery = match prepare(&WebhookEvent::Test) {
+            Some(delivery) => delivery,
+            None => return vec![],
+        };
+
+        let results = self.hooks.iter().map(|hook| {
+            let delivery = delivery.clone();
+
+            async move {
+                let result = send(&self.client, hook, &delivery)
+                    .await
+                    .map_err(|err| err.to_string());
+
+                (hook.url.clone(), result)
+            }
+        });
+
+        futures::future::join_all(results).await
+    }
+}
+
+/// Sends the event to every webhook subscribed to it, in the background
+pub fn fire(data: &TypeMap, event: WebhookEvent) {
+    let webhooks = data.get::<WebhooksKey>().unwrap().clone();
+
+    let hooks = webhooks
+        .hooks
+        .iter()
+        .filter(|hook| {
+            event
+                .kind()
+                .is_none_or(|kind| hook.events.is_empty() || hook.events.contains(&kind))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if hooks.is_empty() {
+        return;
+    }
+
+    let delivery = match prepare(&event) {
+        Some(delivery) => delivery,
+        None => return,
+    };
+
+    for hook in hooks {
+        tokio::spawn(deliver(
+            webhooks.client.clone(),
+            hook,
+            delivery.clone(),
+            INITIAL_BACKOFF,
+        ));
+    }
+}
+
+fn prepare(event: &WebhookEvent) -> Option<Delivery> {
+    let payload = Payload {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        event,
+    };
+
+    match serde_json::to_vec(&payload) {
+        Ok(body) => Some(Delivery {
+            id: format!("{:016x}", rand::thread_rng().gen::<u64>()),
+            event: event.name(),
+            body: Arc::new(body),
+        }),
+        Err(err) => {
+            error!(event = event.name(), %err, "Failed to serialize webhook event");
+            None
+        }
+    }
+}
+
+// the backoff doubles after every failed attempt
+async fn deliver(
+    client: reqwest::Client,
+    hook: WebhookConfig,
+    delivery: Delivery,
+    initial_backoff: Duration,
+) {
+    let mut backoff = initial_backoff;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        match send(&client, &hook, &delivery).await {
+            Ok(()) => {
+                debug!(url = %hook.url, event = delivery.event, attempt, "Delivered webhook");
+                return;
+            }
+            Err(err) if err.retryable && attempt < MAX_ATTEMPTS => {
+                warn!(url = %hook.url, event = delivery.event, attempt, %err, "Webhook delivery failed; retrying");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(err) => {
+                error!(url = %hook.url, event = delivery.event, attempt, %err, "Webhook delivery failed");
+                return;
+            }
+        }
+    }
+}
+
+async fn send(
+    client: &reqwest::Client,
+    hook: &WebhookConfig,
+    delivery: &Delivery,
+) -> Result<(), DeliveryError> {
+    let mut request = client
+        .post(&hook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Webhook-Event", delivery.event)
+        .header("X-Webhook-Delivery", &delivery.id);
+
+    if let Some(secret) = &hook.secret {
+        request = request.header("X-Signature-256", sign(secret, &delivery.body));
+    }
+
+    let response = request
+        .body(delivery.body.as_ref().clone())
+        .send()
+        .await
+        .map_err(|err| DeliveryError {
+            message: err.to_string(),
+            retryable: true,
+        })?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    Err(DeliveryError {
+        message: format!("Endpoint responded with {}", status),
+        retryable: status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+    })
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    // hmac accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::mock_http::{MockServer, Received};
+
+    const BACKOFF: Duration = Duration::from_millis(20);
+
+    // answers with the responses in order, then 200 for every later request
+    fn serve(responses: Vec<StatusCode>) -> MockServer {
+        let responses = Mutex::new(responses);
+
+        MockServer::serve(move |_| {
+            let mut responses = responses.lock().unwrap();
+            if responses.is_empty() {
+                (StatusCode::OK, String::new())
+            } else {
+                (responses.remove(0), String::new())
+            }
+        })
+    }
+
+    fn hook(receiver: &MockServer, secret: Option<&str>) -> WebhookConfig {
+        WebhookConfig {
+            url: receiver.url("/hook"),
+            secret: secret.map(str::to_string),
+            events: Default::default(),
+        }
+    }
+
+    async fn deliver_to(receiver: &MockServer, secret: Option<&str>, event: &WebhookEvent) {
+        deliver(
+            reqwest::Client::new(),
+            hook(receiver, secret),
+            prepare(event).unwrap(),
+            BACKOFF,
+        )
+        .await;
+    }
+
+    fn header<'a>(request: &'a Received, name: &str) -> &'a str {
+        request.headers[name].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_event() {
+        let receiver = serve(vec![]);
+        let event = WebhookEvent::Crashed {
+            server: "valheim".to_string(),
+            previous_status: "Running".to_string(),
+        };
+
+        deliver_to(&receiver, Some("secret"), &event).await;
+
+        let received = receiver.received();
+        assert_eq!(received.len(), 1);
+
+        let request = &received[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.uri.path(), "/hook");
+        assert_eq!(header(request, "content-type"), "application/json");
+        assert_eq!(header(request, "x-webhook-event"), "crashed");
+
+        let body = serde_json::from_slice::<serde_json::Value>(&request.body).unwrap();
+        assert_eq!(body["event"], "crashed");
+        assert_eq!(body["server"], "valheim");
+        assert_eq!(body["previous_status"], "Running");
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(&request.body);
+        let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        assert_eq!(header(request, "x-signature-256"), expected);
+    }
+
+    #[tokio::test]
+    async fn omits_signature_without_secret() {
+        let receiver = serve(vec![]);
+
+        deliver_to(&receiver, None, &WebhookEvent::Test).await;
+
+        let received = receiver.received();
+        assert_eq!(received.len(), 1);
+        assert!(!received[0].headers.contains_key("x-signature-256"));
+    }
+
+    #[tokio::test]
+    async fn retries_with_backoff() {
+        let receiver = serve(vec![
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::TOO_MANY_REQUESTS,
+        ]);
+
+        deliver_to(&receiver, Some("secret"), &WebhookEvent::Test).await;
+
+        let received = receiver.received();
+        assert_eq!(received.len(), 3);
+
+        // every attempt is the same delivery, with the same signature
+        for request in &received[1..] {
+            assert_eq!(request.body, received[0].body);
+            assert_eq!(
+                header(request, "x-webhook-delivery"),
+                header(&received[0], "x-webhook-delivery")
+            );
+            assert_eq!(
+                header(request, "x-signature-256"),
+                header(&received[0], "x-signature-256")
+            );
+        }
+
+        assert!(received[1].at - received[0].at >= BACKOFF);
+        assert!(received[2].at - received[1].at >= BACKOFF * 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let receiver = serve(vec![StatusCode::BAD_GATEWAY; MAX_ATTEMPTS as usize + 1]);
+
+        deliver_to(&receiver, None, &WebhookEvent::Test).await;
+
+        assert_eq!(receiver.received().len(), MAX_ATTEMPTS as usize);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let receiver = serve(vec![StatusCode::BAD_REQUEST]);
+
+        deliver_to(&receiver, None, &WebhookEvent::Test).await;
+
+        assert_eq!(receiver.received().len(), 1);
+    }
+}